@@ -0,0 +1,57 @@
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dbz_sagas_extractor::{FromReader, SagasColorLUT, SagasFile};
+use image::RgbaImage;
+
+const FIXTURE: &[u8] = include_bytes!("fixtures/pattern_256.dbl");
+
+// The fixture with its header patched to `size` x `size` and the image data
+// tiled to match.
+fn file_of_size(size: u16) -> Vec<u8> {
+    let sf = SagasFile::from_reader(&mut Cursor::new(FIXTURE)).unwrap();
+    let image_offset = sf.get_header().image_offset as usize;
+
+    let mut data = FIXTURE[..image_offset].to_vec();
+    for offset in [0x48, 0x4a] {
+        data[offset..offset + 2].copy_from_slice(&size.to_le_bytes());
+    }
+    let pixels = size as usize * size as usize;
+    data.extend(sf.get_image().iter().cycle().take(pixels));
+    data
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for size in [64, 256, 512] {
+        let data = file_of_size(size);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| SagasFile::from_reader(&mut Cursor::new(black_box(data.as_slice()))).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn deswizzle(c: &mut Criterion) {
+    // Deswizzling twice is a no-op, so the same table can be reused.
+    let colors = SagasFile::from_reader(&mut Cursor::new(FIXTURE)).unwrap()
+        .get_color_table().colors.clone();
+    let mut lut = SagasColorLUT { colors };
+    c.bench_function("deswizzle", |b| b.iter(|| black_box(&mut lut).deswizzle()));
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    for size in [64, 256, 512] {
+        let sf = SagasFile::from_reader(&mut Cursor::new(file_of_size(size))).unwrap();
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &sf, |b, sf| {
+            b.iter(|| RgbaImage::try_from(black_box(sf)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, deswizzle, render);
+criterion_main!(benches);
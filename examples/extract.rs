@@ -0,0 +1,40 @@
+// Decodes a Sagas portrait and saves it as a PNG, using only the public API.
+//
+//     cargo run --example extract -- data/raditz.dbl raditz.png
+
+use std::env;
+use std::process;
+
+use dbz_sagas_extractor::{parse_file, SagasError};
+use image::RgbaImage;
+
+fn run(input: &str, output: &str) -> Result<(), SagasError> {
+    // Opens, buffers and parses the whole file: header, palette and pixels.
+    let sf = parse_file(input)?;
+
+    // The header holds the dimensions and the path of the asset the image was
+    // made from on the developers' machines.
+    let header = sf.get_header();
+    println!("{}x{}, from {}", header.width, header.height, header.source_path());
+
+    // Looks every pixel's palette index up in the (already deswizzled) color
+    // table. This fails instead of panicking if an index is out of range.
+    let image = RgbaImage::try_from(&sf)?;
+
+    // From here on it's a plain `image` buffer.
+    image.save(output)?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <input.dbl> <output.png>", args[0]);
+        process::exit(2);
+    }
+
+    if let Err(e) = run(&args[1], &args[2]) {
+        eprintln!("{}: {}", args[1], e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,27 @@
+// Feeds arbitrary bytes to the parsers, which must return an error instead
+// of panicking however malformed the input is. Run it from the repository
+// root with a nightly toolchain and cargo-fuzz installed:
+//
+//     cargo install cargo-fuzz
+//     cargo +nightly fuzz run parse
+//
+// Seeding the corpus with real files helps it get past the header quickly:
+//
+//     mkdir -p fuzz/corpus/parse && cp data/*.dbl fuzz/corpus/parse/
+#![no_main]
+
+use std::io::Cursor;
+
+use dbz_sagas_extractor::{parse_bytes, DecodeOptions, SagasArchive, SagasFile};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(sf) = parse_bytes(data) {
+        // Everything derived from a parsed file has to hold up as well.
+        let _ = sf.check_indices();
+        let _ = sf.record_len();
+        let _ = sf.get_color_table().render(&sf.index_image());
+    }
+    let _ = SagasFile::read_header_and_palette(&mut Cursor::new(data));
+    let _ = SagasArchive::from_reader_with(&mut Cursor::new(data), &DecodeOptions::default());
+});
@@ -0,0 +1,51 @@
+use std::io::{BufRead, Seek, SeekFrom};
+
+use crate::{DecodeOptions, FromReader, Result, SagasError, SagasFile};
+
+// Several Sagas files stored back to back, as found in some dumps.
+#[derive(Debug)]
+pub struct SagasArchive {
+    pub files: Vec<SagasFile>,
+}
+
+impl SagasArchive {
+    // Reads files until the data after the last one doesn't start with the
+    // portrait signature, either right at its end or at the next 16 byte
+    // boundary (PS2 data is usually 16 byte aligned). The first file must be
+    // valid, trailing garbage is ignored.
+    pub fn from_reader_with<R>(r: &mut R, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        if !SagasFile::probe(r) {
+            return Err(SagasError::BadSignature);
+        }
+
+        let mut files = Vec::new();
+        loop {
+            let start = r.stream_position()?;
+            let file = SagasFile::from_reader_with(r, options)?;
+            let end = start + file.record_len()?;
+            files.push(file);
+
+            let next = [end, end.next_multiple_of(16)].into_iter().find(|&offset| {
+                r.seek(SeekFrom::Start(offset)).is_ok() && SagasFile::probe(r)
+            });
+            match next {
+                Some(offset) => r.seek(SeekFrom::Start(offset))?,
+                None => break,
+            };
+        }
+
+        Ok(SagasArchive {
+            files,
+        })
+    }
+}
+
+impl<R> FromReader<R> for SagasArchive
+    where R : BufRead + Seek
+{
+    fn from_reader(r: &mut R) -> Result<Self> {
+        SagasArchive::from_reader_with(r, &DecodeOptions::default())
+    }
+}
@@ -0,0 +1,244 @@
+use byteorder::ReadBytesExt;
+use std::fmt;
+use std::io::{BufRead, Seek, Write};
+use std::str::FromStr;
+
+use crate::{AlphaMode, DecodeOptions, FromReader, Result, SagasError, ToWriter};
+
+// Inverse of `AlphaMode::Double`: brings 0-255 alpha back to the stored
+// 0-128 range.
+fn compress_alpha(a: u8) -> u8 {
+    ((a as u16 + 1) >> 1) as u8
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SagasColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SagasColorLUT {
+    pub colors: Vec<SagasColor>,
+}
+
+impl SagasColor {
+    pub fn from_reader_with<R>(rd: &mut R, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        let (r,g, b, a) = (
+            rd.read_u8()?,
+            rd.read_u8()?,
+            rd.read_u8()?,
+            rd.read_u8()?,
+        );
+
+        let a = options.alpha_mode.apply_with_max(a, options.alpha_max);
+
+        Ok(SagasColor {
+            r,
+            g,
+            b,
+            a
+        })
+    }
+}
+
+impl SagasColor {
+    // The color as it's displayed, with alpha already decoded.
+    pub fn to_rgba(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    // The color in linear light, taking the stored RGB as sRGB. Alpha is
+    // only scaled to 0-1.
+    pub fn to_linear_rgba(&self) -> [f32; 4] {
+        let linear = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        [linear(self.r), linear(self.g), linear(self.b), self.a as f32 / 255.0]
+    }
+
+    // Perceived brightness (Rec. 601 weights), 0-255.
+    pub fn luminance(&self) -> f32 {
+        0.299 * self.r as f32 + 0.587 * self.g as f32 + 0.114 * self.b as f32
+    }
+
+    // A color as close to `rgba` as the file can store. Stored alpha only has
+    // 129 steps, so even alpha values other than 0 come back one lower
+    // (e.g. 2 turns into 1); `from_rgba(c.to_rgba())` is stable after that.
+    pub fn from_rgba(rgba: [u8; 4]) -> SagasColor {
+        let [r, g, b, a] = rgba;
+        SagasColor {
+            r,
+            g,
+            b,
+            a: AlphaMode::Double.apply(compress_alpha(a)),
+        }
+    }
+}
+
+// `#rrggbbaa`, with the decoded alpha.
+impl fmt::Display for SagasColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+    }
+}
+
+// Parses `#RRGGBB` (opaque) or `#RRGGBBAA`, in either case. The `#` may be
+// left out.
+impl FromStr for SagasColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("expected #RRGGBB or #RRGGBBAA but got '{}'", s));
+        }
+        let byte = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        Ok(SagasColor {
+            r: byte(0),
+            g: byte(1),
+            b: byte(2),
+            a: if hex.len() == 8 { byte(3) } else { 255 },
+        })
+    }
+}
+
+impl<R> FromReader<R> for SagasColor
+    where R : BufRead + Seek
+{
+    fn from_reader(rd: &mut R) -> Result<Self> {
+        SagasColor::from_reader_with(rd, &DecodeOptions::default())
+    }
+}
+
+impl SagasColorLUT {
+    // Reads `options.palette_size` entries, or 256 when not set.
+    pub fn from_reader_with<R>(r: &mut R, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        SagasColorLUT::from_reader_sized(r, options.palette_size.unwrap_or(256), options)
+    }
+
+    pub fn from_reader_sized<R>(r: &mut R, num_colors: usize, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        // The count can come from a damaged header, so don't trust it with
+        // more than a full palette up front.
+        let mut colors = Vec::with_capacity(num_colors.min(256));
+        for _ in 0..num_colors {
+            colors.push(SagasColor::from_reader_with(r, options)?);
+        }
+
+        let mut lut = SagasColorLUT {
+            colors,
+        };
+        if options.deswizzle {
+            lut.deswizzle();
+        }
+        Ok(lut)
+    }
+
+    // Makes entry `index` fully transparent, for portraits that use a color
+    // key instead of palette alpha.
+    pub fn set_transparent_index(&mut self, index: u8) -> Result<()> {
+        let palette_size = self.colors.len();
+        match self.colors.get_mut(index as usize) {
+            Some(c) => {
+                c.a = 0;
+                Ok(())
+            },
+            None => Err(SagasError::IndexOutOfRange { index, palette_size }),
+        }
+    }
+
+    // Makes every entry with the given RGB value fully transparent and returns
+    // how many matched.
+    pub fn set_transparent_color(&mut self, [r, g, b]: [u8; 3]) -> usize {
+        let mut matched = 0;
+        for c in self.colors.iter_mut().filter(|c| (c.r, c.g, c.b) == (r, g, b)) {
+            c.a = 0;
+            matched += 1;
+        }
+        matched
+    }
+
+    // Maps every entry's alpha to `a * scale + offset`, clamped to 0-255.
+    pub fn remap_alpha(&mut self, scale: f32, offset: i32) {
+        for c in &mut self.colors {
+            c.a = (c.a as f32 * scale + offset as f32).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    // Undoes the PS2 palette swizzle. The swap is its own inverse, so calling
+    // this on a deswizzled table swizzles it again. Only whole 32 entry blocks
+    // are swizzled, so small (16 color) tables are left alone.
+    pub fn deswizzle(&mut self) {
+        deswizzle_palette(&mut self.colors, 32);
+    }
+
+    // A copy ordered from dark to light, ties broken by alpha. Only meant for
+    // looking at palettes, the indices no longer match the image.
+    pub fn sorted_by_luminance(&self) -> SagasColorLUT {
+        let mut colors = self.colors.clone();
+        colors.sort_by(|a, b| a.luminance().total_cmp(&b.luminance()).then(a.a.cmp(&b.a)));
+        SagasColorLUT { colors }
+    }
+}
+
+// The PS2 GS stores 256 color CLUTs (CSM1 layout) as 8 entry rows where
+// every second and third row of each 32 entry block are exchanged: stored
+// entries 8-15 hold colors 16-23 and vice versa. This swaps the second and
+// third quarter of every whole `stride` sized block, so `stride` 32 undoes
+// (or applies) that pattern. A trailing partial block is left as is.
+pub fn deswizzle_palette(colors: &mut [SagasColor], stride: usize) {
+    let quarter = stride / 4;
+    if quarter == 0 {
+        return;
+    }
+    for block in colors.chunks_exact_mut(stride) {
+        let (second, third) = block[quarter..3 * quarter].split_at_mut(quarter);
+        second.swap_with_slice(third);
+    }
+}
+
+impl<R> FromReader<R> for SagasColorLUT
+    where R : BufRead + Seek
+{
+    fn from_reader(r: &mut R) -> Result<Self> {
+        SagasColorLUT::from_reader_with(r, &DecodeOptions::default())
+    }
+}
+
+// Writes the color back in stored form, undoing the default `double` alpha
+// expansion.
+impl<W> ToWriter<W> for SagasColor
+    where W : Write + Seek
+{
+    fn to_writer(&self, w: &mut W) -> Result<()> {
+        w.write_all(&[self.r, self.g, self.b, compress_alpha(self.a)])?;
+        Ok(())
+    }
+}
+
+// Writes the table back in stored (swizzled) order.
+impl<W> ToWriter<W> for SagasColorLUT
+    where W : Write + Seek
+{
+    fn to_writer(&self, w: &mut W) -> Result<()> {
+        let mut swizzled = SagasColorLUT {
+            colors: self.colors.clone(),
+        };
+        swizzled.deswizzle();
+        for c in &swizzled.colors {
+            c.to_writer(w)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use crate::{Result, SagasColor, SagasColorLUT, SagasError, SagasFile, SagasHeader};
+
+const NUM_COLORS: usize = 256;
+
+// Header of a retail 8-bit portrait, used when no template is given.
+fn default_header() -> SagasHeader {
+    SagasHeader {
+        unk0: 1,
+        unk1: 0x90,
+        unk2: 1,
+        unk3: 1,
+        unk4: 128,
+        string0: CString::new("c:\\dev\\dbz\\data_ps2\\sprites\\fac").unwrap(),
+        unk5: 0x409,
+        unk6: 0,
+        unk7: 0,
+        image_offset: 0xa0 + 4 * NUM_COLORS as u32,
+        width: 0,
+        height: 0,
+        unk9: 0,
+        unk10: 0,
+        color_table_offset: 0xa0,
+        unk12: 16,
+        unk13: 16,
+        unk14: 64,
+        string1: CString::new("Bit08_000.tga").unwrap(),
+    }
+}
+
+// Palette and indices for an image with at most 256 distinct colors, which
+// can be stored without any loss.
+fn exact_palette(image: &RgbaImage) -> Option<(Vec<SagasColor>, Vec<u8>)> {
+    let mut colors = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.len() / 4);
+    for pixel in image.pixels() {
+        let index = *lookup.entry(pixel.0).or_insert_with(|| {
+            colors.push(SagasColor::from_rgba(pixel.0));
+            colors.len() - 1
+        });
+        if index >= NUM_COLORS {
+            return None;
+        }
+        indices.push(index as u8);
+    }
+    Some((colors, indices))
+}
+
+// NeuQuant palette for images with too many colors, each pixel is mapped to
+// its nearest entry. Fully transparent pixels get an entry of their own and
+// are left out of the training, their color is never seen anyway.
+fn quantized_palette(image: &RgbaImage) -> (Vec<SagasColor>, Vec<u8>) {
+    let transparent = image.pixels().any(|p| p.0[3] == 0);
+    let visible: Vec<u8> = image.pixels().filter(|p| p.0[3] != 0).flat_map(|p| p.0).collect();
+    let num_colors = if transparent { NUM_COLORS - 1 } else { NUM_COLORS };
+
+    let quant = color_quant::NeuQuant::new(10, num_colors, &visible);
+    let mut colors: Vec<SagasColor> = quant.color_map_rgba()
+        .chunks(4)
+        .map(|c| SagasColor::from_rgba([c[0], c[1], c[2], c[3]]))
+        .collect();
+    if transparent {
+        colors.push(SagasColor { r: 0, g: 0, b: 0, a: 0 });
+    }
+    let indices = image.pixels().map(|p| nearest(&colors, p.0)).collect();
+    (colors, indices)
+}
+
+// Index of the palette entry closest to `rgba`. Color differences count for
+// less the more transparent the pixel is, since they show less, while alpha
+// counts double. A fully transparent pixel so ends up on the most
+// transparent entry whatever its color.
+fn nearest(colors: &[SagasColor], rgba: [u8; 4]) -> u8 {
+    let distance = |c: &SagasColor| {
+        let d = |x: u8, y: u8| (x as i64 - y as i64).pow(2);
+        let color = d(c.r, rgba[0]) + d(c.g, rgba[1]) + d(c.b, rgba[2]);
+        color * rgba[3] as i64 / 255 + 2 * d(c.a, rgba[3])
+    };
+    (0..colors.len()).min_by_key(|&i| distance(&colors[i])).unwrap_or(0) as u8
+}
+
+// Builds an 8-bit Sagas file from an RGBA image, quantizing it to 256 colors
+// if needed.
+//
+// When a template is given its header and reserved bytes are copied and only
+// the dimensions, palette size and image offset are updated. The game's
+// loader hasn't been reverse-engineered, so the safest option is to use a
+// retail portrait of the same slot as the template. Fields known to matter:
+//
+// * `unk0`/`unk1` are the file signature.
+// * `unk12`/`unk13` are the palette dimensions (16x16) and `string1` names
+//   the bit depth (`Bit08_...`), both are reset for the 8-bit output.
+// * The reserved block after the header repeats the dimensions at 0x84, which
+//   is updated when it matches the template's. The rest is copied verbatim.
+//
+// `unk4`, `unk5` and `unk14` vary with the texture's memory layout and should
+// be copied from a template with the same dimensions.
+//
+// The output is always little-endian like the PS2 files, also when the
+// template was read as big-endian.
+pub fn encode_rgba(image: &RgbaImage, template: Option<&SagasFile>) -> Result<SagasFile> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(SagasError::BadDimensions);
+    }
+
+    let (mut header, mut reserved) = match template {
+        Some(template) => (template.get_header().clone(), template.reserved().to_vec()),
+        None => (default_header(), Vec::new()),
+    };
+
+    let old_dims = [header.width.to_le_bytes(), header.height.to_le_bytes()].concat();
+    let new_dims = [(width as u16).to_le_bytes(), (height as u16).to_le_bytes()].concat();
+    if let Some(dims_at) = 0x84usize.checked_sub(header.size()) {
+        if let Some(dims) = reserved.get_mut(dims_at..dims_at + 4).filter(|dims| **dims == *old_dims) {
+            dims.copy_from_slice(&new_dims);
+        }
+    }
+
+    header.width = width as u16;
+    header.height = height as u16;
+    header.unk12 = 16;
+    header.unk13 = 16;
+    if header.bits_per_pixel() != 8 {
+        // Same length, so the reserved block stays where it was.
+        let mut name = header.string1.as_bytes().to_vec();
+        name[3..5].copy_from_slice(b"08");
+        header.string1 = CString::new(name)?;
+    }
+    // The palette goes first, where the template's first data section was,
+    // and the image right after it, so a bigger image can't run into it.
+    let data_start = (header.size() + reserved.len()) as u32;
+    if header.color_table_offset < data_start || header.image_offset < header.color_table_offset {
+        header.color_table_offset = data_start;
+    }
+    header.image_offset = header.color_table_offset + 4 * NUM_COLORS as u32;
+
+    let (mut colors, indices) = exact_palette(image).unwrap_or_else(|| quantized_palette(image));
+    colors.resize(NUM_COLORS, SagasColor { r: 0, g: 0, b: 0, a: 0 });
+
+    Ok(SagasFile::from_raw_parts(header, reserved, SagasColorLUT { colors }, indices, 8))
+}
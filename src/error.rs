@@ -0,0 +1,125 @@
+use std::{error, ffi, fmt, io};
+
+#[derive(Debug)]
+pub enum SagasError {
+    Io(io::Error),
+    Image(image::ImageError),
+    BadSignature,       // doesn't start with the portrait signature
+    BadString(ffi::NulError), // embedded string contains a NUL byte
+    Truncated,          // stream ended before a field or data section was complete
+    BadDimensions,      // width/height don't match the data available
+    UnsupportedBitDepth(u8), // image data that isn't 4 or 8 bits per pixel
+    StrideTooSmall { stride: usize, row_bytes: usize },
+    IndexOutOfRange { index: u8, palette_size: usize },
+    PaletteSizeMismatch { expected: usize, found: usize }, // the color table doesn't match the header
+    CropOutOfBounds { x: u32, y: u32, width: u32, height: u32 },
+    UnevenAtlas { width: u32, height: u32, columns: u32, rows: u32 }, // the grid doesn't divide the image
+    OffsetOutOfRange { offset: u64, len: u64 }, // a header offset points at or past the end of the stream
+    NoRoomForPalette { image_offset: u32, palette_bytes: usize }, // between the header and the image
+    BadPalette(String), // external palette file couldn't be parsed
+    FrameSizeMismatch { frame: usize, expected: (u32, u32), found: (u32, u32) },
+    #[cfg(feature = "exr")]
+    Exr(exr::error::Error),
+}
+
+pub type Result<T> = std::result::Result<T, SagasError>;
+
+impl fmt::Display for SagasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SagasError::Io(e) => write!(f, "I/O error: {}", e),
+            SagasError::Image(e) => write!(f, "image error: {}", e),
+            SagasError::BadSignature => write!(f, "not a Sagas portrait"),
+            SagasError::BadString(e) => write!(f, "invalid embedded string: {}", e),
+            SagasError::Truncated => write!(f, "file is truncated"),
+            SagasError::BadDimensions => write!(f, "image dimensions don't match the file contents"),
+            SagasError::UnsupportedBitDepth(bits) => write!(f, "unsupported bit depth of {} bits per pixel, expected 4 or 8", bits),
+            SagasError::StrideTooSmall { stride, row_bytes } => {
+                write!(f, "stride of {} bytes is smaller than a {} byte row", stride, row_bytes)
+            },
+            SagasError::IndexOutOfRange { index, palette_size } => {
+                write!(f, "palette index {} is out of range for a {} color palette", index, palette_size)
+            },
+            SagasError::PaletteSizeMismatch { expected, found } => {
+                write!(f, "the header describes a {} color palette but {} colors were given", expected, found)
+            },
+            SagasError::CropOutOfBounds { x, y, width, height } => {
+                write!(f, "crop {}x{} at {},{} doesn't fit in the image", width, height, x, y)
+            },
+            SagasError::UnevenAtlas { width, height, columns, rows } => {
+                write!(f, "a {}x{} image can't be split into {} columns and {} rows of equal size", width, height, columns, rows)
+            },
+            SagasError::OffsetOutOfRange { offset, len } => {
+                write!(f, "offset {:#x} is past the end of the file ({} bytes)", offset, len)
+            },
+            SagasError::NoRoomForPalette { image_offset, palette_bytes } => {
+                write!(f, "no room for a {} byte palette between the header and the image at {:#x}", palette_bytes, image_offset)
+            },
+            SagasError::BadPalette(reason) => write!(f, "invalid palette file: {}", reason),
+            SagasError::FrameSizeMismatch { frame, expected, found } => {
+                write!(f, "frame {} is {}x{} but the animation is {}x{}", frame, found.0, found.1, expected.0, expected.1)
+            },
+            #[cfg(feature = "exr")]
+            SagasError::Exr(e) => write!(f, "EXR error: {}", e),
+        }
+    }
+}
+
+impl error::Error for SagasError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SagasError::Io(e) => Some(e),
+            SagasError::Image(e) => Some(e),
+            SagasError::BadString(e) => Some(e),
+            #[cfg(feature = "exr")]
+            SagasError::Exr(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SagasError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => SagasError::Truncated,
+            _ => SagasError::Io(e),
+        }
+    }
+}
+
+impl From<image::ImageError> for SagasError {
+    fn from(e: image::ImageError) -> Self {
+        SagasError::Image(e)
+    }
+}
+
+impl From<png::EncodingError> for SagasError {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => SagasError::Io(e),
+            e => SagasError::Io(io::Error::other(e)),
+        }
+    }
+}
+
+impl From<ffi::NulError> for SagasError {
+    fn from(e: ffi::NulError) -> Self {
+        SagasError::BadString(e)
+    }
+}
+
+impl From<gif::EncodingError> for SagasError {
+    fn from(e: gif::EncodingError) -> Self {
+        match e {
+            gif::EncodingError::Io(e) => SagasError::Io(e),
+            e => SagasError::Io(io::Error::other(e)),
+        }
+    }
+}
+
+#[cfg(feature = "exr")]
+impl From<exr::error::Error> for SagasError {
+    fn from(e: exr::error::Error) -> Self {
+        SagasError::Exr(e)
+    }
+}
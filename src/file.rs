@@ -0,0 +1,343 @@
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
+
+use crate::{pack_4bpp, unpack_4bpp, DecodeOptions, FromReader, Result, SagasColorLUT, SagasError, SagasHeader, ToWriter};
+
+// Every known portrait starts with `unk0 = 1` (u64) followed by `unk1 = 0x90` (u32).
+const SIGNATURE: [u8; 12] = [0x01, 0, 0, 0, 0, 0, 0, 0, 0x90, 0, 0, 0];
+const SIGNATURE_BE: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 0x01, 0, 0, 0, 0x90];
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| (hash ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+#[derive(Debug)]
+pub struct SagasFile {
+    header: SagasHeader,
+    reserved: Vec<u8>, // unparsed bytes between the header and the first data section
+    lut: SagasColorLUT,
+    image: Vec<u8>, // one palette index per pixel, unpacked for 4-bit files
+    bits_per_pixel: u8,
+    stride: Option<usize>, // bytes per stored row, if rows were padded
+}
+
+// What `SagasFile::read_header_and_palette` parsed. `image` is always `None`,
+// the image data isn't read at all.
+#[derive(Debug)]
+pub struct SagasParts {
+    pub header: SagasHeader,
+    pub lut: SagasColorLUT,
+    pub image: Option<Vec<u8>>,
+}
+
+impl SagasFile {
+    // Offsets in the header are relative to where the file starts, which
+    // isn't the start of the stream for files inside an archive.
+    pub fn from_reader_with<R>(r: &mut R, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        let Start { base, len, header, reserved, lut } = Start::read(r, options)?;
+        let (width, height) = (header.width as usize, header.height as usize);
+        let in_range = |offset: u32| {
+            let offset = base + offset as u64;
+            if offset < len { Ok(offset) } else { Err(SagasError::OffsetOutOfRange { offset, len }) }
+        };
+
+        let bits_per_pixel = options.bits_per_pixel.unwrap_or_else(|| header.bits_per_pixel());
+        let row_bytes = match bits_per_pixel {
+            4 => width.div_ceil(2),
+            8 => width,
+            bits => return Err(SagasError::UnsupportedBitDepth(bits)),
+        };
+        let num_bytes = match options.stride {
+            Some(stride) if stride < row_bytes => return Err(SagasError::StrideTooSmall { stride, row_bytes }),
+            Some(stride) => stride.checked_mul(height).ok_or(SagasError::BadDimensions)?,
+            None if bits_per_pixel == 4 => (width * height).div_ceil(2),
+            None => width * height,
+        };
+
+        // Make sure the declared image actually fits in the file.
+        let image_offset = in_range(header.image_offset)?;
+        if len < image_offset + num_bytes as u64 {
+            return Err(SagasError::BadDimensions);
+        }
+
+        // Start reading the image.
+        r.seek(SeekFrom::Start(image_offset))?;
+        let mut image = vec![0; num_bytes];
+        r.read_exact(&mut image)?;
+        // Padded rows are unpacked one at a time, dropping the padding.
+        if let Some(stride) = options.stride {
+            image = image.chunks_exact(stride)
+                .flat_map(|row| match bits_per_pixel {
+                    4 => unpack_4bpp(&row[..row_bytes], width),
+                    _ => row[..width].to_vec(),
+                })
+                .collect();
+        } else if bits_per_pixel == 4 {
+            image = unpack_4bpp(&image, width * height);
+        }
+
+        Ok(SagasFile {
+            header,
+            reserved,
+            lut,
+            image,
+            bits_per_pixel,
+            stride: options.stride,
+        })
+    }
+
+    // Reads only the header and the color table, for when the image isn't
+    // needed. The stream is left right after the color table.
+    pub fn read_header_and_palette<R>(r: &mut R) -> Result<SagasParts>
+        where R : BufRead + Seek
+    {
+        SagasFile::read_header_and_palette_with(r, &DecodeOptions::default())
+    }
+
+    pub fn read_header_and_palette_with<R>(r: &mut R, options: &DecodeOptions) -> Result<SagasParts>
+        where R : BufRead + Seek
+    {
+        let start = Start::read(r, options)?;
+        Ok(SagasParts { header: start.header, lut: start.lut, image: None })
+    }
+
+    // Assembles a file for writing, checking that `image` has one index per
+    // pixel of the header's (non-zero) size, that each fits the depth and is
+    // within `lut`, and that `lut` has the header's palette size. The depth
+    // is the header's `bits_per_pixel`, and nothing sits between the header
+    // and the data.
+    pub fn from_parts(header: SagasHeader, lut: SagasColorLUT, image: Vec<u8>) -> Result<Self> {
+        if header.width == 0 || header.height == 0
+            || header.width as usize * header.height as usize != image.len() {
+            return Err(SagasError::BadDimensions);
+        }
+        let bits_per_pixel = header.bits_per_pixel();
+        let max_colors = 1usize << bits_per_pixel;
+        if let Some(&index) = image.iter().find(|&&i| i as usize >= max_colors) {
+            return Err(SagasError::IndexOutOfRange { index, palette_size: max_colors });
+        }
+        let palette_size = header.palette_size();
+        let sf = SagasFile::from_raw_parts(header, Vec::new(), lut, image, bits_per_pixel);
+        sf.check_indices()?;
+        if sf.lut.colors.len() != palette_size {
+            return Err(SagasError::PaletteSizeMismatch { expected: palette_size, found: sf.lut.colors.len() });
+        }
+        Ok(sf)
+    }
+
+    pub(crate) fn from_raw_parts(header: SagasHeader, reserved: Vec<u8>, lut: SagasColorLUT,
+                                 image: Vec<u8>, bits_per_pixel: u8) -> Self {
+        SagasFile {
+            header,
+            reserved,
+            lut,
+            image,
+            bits_per_pixel,
+            stride: None,
+        }
+    }
+
+    // Checks whether the stream starts with the portrait signature, in either
+    // byte order. The stream position is left where it was.
+    pub fn probe<R>(r: &mut R) -> bool
+        where R : BufRead + Seek
+    {
+        let start = match r.stream_position() {
+            Ok(start) => start,
+            Err(_) => return false,
+        };
+
+        let mut magic = [0u8; SIGNATURE.len()];
+        let matched = r.read_exact(&mut magic).is_ok() && (magic == SIGNATURE || magic == SIGNATURE_BE);
+        r.seek(SeekFrom::Start(start)).is_ok() && matched
+    }
+
+    // Makes sure every pixel refers to an entry that exists in the color table.
+    pub fn check_indices(&self) -> Result<()> {
+        let palette_size = self.lut.colors.len();
+        match self.image.iter().find(|&&i| i as usize >= palette_size) {
+            Some(&index) => Err(SagasError::IndexOutOfRange { index, palette_size }),
+            None => Ok(()),
+        }
+    }
+
+    // 64-bit FNV-1a hash of the palette indices, the same on every platform
+    // and Rust version so it can be stored and compared later.
+    pub fn index_hash(&self) -> u64 {
+        fnv1a(FNV_OFFSET_BASIS, &self.image)
+    }
+
+    // Hash of what the image looks like: its dimensions, decoded palette and
+    // indices. The `unk*` fields and strings are left out, so files that only
+    // differ there hash the same. Stable like `index_hash`.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, &self.header.width.to_le_bytes());
+        hash = fnv1a(hash, &self.header.height.to_le_bytes());
+        for c in &self.lut.colors {
+            hash = fnv1a(hash, &[c.r, c.g, c.b, c.a]);
+        }
+        fnv1a(hash, &self.image)
+    }
+
+    pub fn get_header(&self) -> &SagasHeader {
+        &self.header
+    }
+
+    pub fn get_color_table(&self) -> &SagasColorLUT {
+        &self.lut
+    }
+
+    pub fn get_color_table_mut(&mut self) -> &mut SagasColorLUT {
+        &mut self.lut
+    }
+
+    pub fn get_image(&self) -> &[u8] {
+        self.image.as_slice()
+    }
+
+    // Number of bytes the file spans from the start of its header to the end
+    // of its last data section.
+    pub fn record_len(&self) -> Result<u64> {
+        let header_end = (self.header.size() + self.reserved.len()) as u64;
+        let palette_end = self.header.color_table_offset as u64 + 4 * self.lut.colors.len() as u64;
+        let image_bytes = match (self.stride, self.bits_per_pixel) {
+            (Some(stride), _) => stride.checked_mul(self.header.height as usize).ok_or(SagasError::BadDimensions)?,
+            (None, 4) => self.image.len().div_ceil(2),
+            (None, _) => self.image.len(),
+        };
+        let image_end = self.header.image_offset as u64 + image_bytes as u64;
+        Ok(header_end.max(palette_end).max(image_end))
+    }
+
+    pub(crate) fn reserved(&self) -> &[u8] {
+        &self.reserved
+    }
+
+    // Bits per pixel the image was stored with, 4 or 8.
+    pub fn bits_per_pixel(&self) -> u8 {
+        self.bits_per_pixel
+    }
+}
+
+// The canonical way to parse a file that's already in memory,
+// `parse_bytes` is a shorthand for it.
+impl TryFrom<&[u8]> for SagasFile {
+    type Error = SagasError;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        SagasFile::from_reader(&mut Cursor::new(data))
+    }
+}
+
+// Everything up to and including the color table, which is read the same
+// way with or without the image.
+struct Start {
+    base: u64, // stream position of the header, which offsets are relative to
+    len: u64,  // of the whole stream
+    header: SagasHeader,
+    reserved: Vec<u8>,
+    lut: SagasColorLUT,
+}
+
+impl Start {
+    fn read<R>(r: &mut R, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        let base = r.stream_position()?;
+        let mut header = SagasHeader::from_reader_with(r, options)?;
+        // Replaced in the header itself, so everything after sees the same size.
+        if let Some((width, height)) = options.dimensions {
+            header.width = width;
+            header.height = height;
+        }
+        header.color_table_offset = options.color_table_offset.unwrap_or(header.color_table_offset);
+        header.image_offset = options.image_offset.unwrap_or(header.image_offset);
+        if header.width == 0 || header.height == 0 {
+            return Err(SagasError::BadDimensions);
+        }
+
+        // Offsets come straight from the header, so check them against the
+        // stream length before seeking anywhere.
+        let header_end = r.stream_position()?;
+        let len = r.seek(SeekFrom::End(0))?;
+        r.seek(SeekFrom::Start(header_end))?;
+
+        // Some variants leave the color table offset unset (zero) and store
+        // the table right before the image. Unless an offset was forced, one
+        // pointing into the header is taken to mean that too. Offsets past the
+        // end stay an error, those files are damaged rather than different.
+        let num_colors = options.palette_size.unwrap_or_else(|| header.palette_size());
+        let unset = (header.color_table_offset as u64) < header_end - base && options.color_table_offset.is_none();
+        if options.inline_palette || unset {
+            let palette_bytes = 4 * num_colors;
+            let fits = (header.image_offset as u64).checked_sub(palette_bytes as u64)
+                .filter(|&offset| offset >= header_end - base && base + header.image_offset as u64 <= len);
+            match fits {
+                Some(offset) => header.color_table_offset = offset as u32,
+                None if options.inline_palette => {
+                    return Err(SagasError::NoRoomForPalette { image_offset: header.image_offset, palette_bytes });
+                },
+                None => {}, // the usual checks below report it
+            }
+        }
+
+        let color_table_offset = base + header.color_table_offset as u64;
+        if color_table_offset >= len {
+            return Err(SagasError::OffsetOutOfRange { offset: color_table_offset, len });
+        }
+
+        // Keep whatever sits between the header and the data so the file can
+        // be written back unchanged.
+        let data_start = base + header.color_table_offset.min(header.image_offset) as u64;
+        let mut reserved = Vec::new();
+        r.take(data_start.saturating_sub(header_end)).read_to_end(&mut reserved)?;
+
+        // Start reading the color table.
+        r.seek(SeekFrom::Start(color_table_offset))?;
+        let lut = SagasColorLUT::from_reader_sized(r, num_colors, options)?;
+
+        Ok(Start { base, len, header, reserved, lut })
+    }
+}
+
+impl<R> FromReader<R> for SagasFile
+    where R : BufRead + Seek
+{
+    fn from_reader(r: &mut R) -> Result<Self> {
+        SagasFile::from_reader_with(r, &DecodeOptions::default())
+    }
+}
+
+// Writes the file back in the layout it was read from. Gaps between the
+// sections other than the one right after the header are zero filled.
+impl<W> ToWriter<W> for SagasFile
+    where W : Write + Seek
+{
+    fn to_writer(&self, w: &mut W) -> Result<()> {
+        let base = w.stream_position()?;
+        self.header.to_writer(w)?;
+        w.write_all(&self.reserved)?;
+
+        w.seek(SeekFrom::Start(base + self.header.color_table_offset as u64))?;
+        self.lut.to_writer(w)?;
+
+        w.seek(SeekFrom::Start(base + self.header.image_offset as u64))?;
+        match (self.stride, self.bits_per_pixel) {
+            (Some(stride), bits_per_pixel) => {
+                // Rows go back at their padded positions, the padding is zero filled.
+                for row in self.image.chunks_exact(self.header.width as usize) {
+                    let row = if bits_per_pixel == 4 { pack_4bpp(row) } else { row.to_vec() };
+                    w.write_all(&row)?;
+                    w.write_all(&vec![0; stride - row.len()])?;
+                }
+            },
+            (None, 4) => w.write_all(&pack_4bpp(&self.image))?,
+            (None, _) => w.write_all(&self.image)?,
+        }
+        Ok(())
+    }
+}
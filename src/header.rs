@@ -0,0 +1,292 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian as L, ReadBytesExt, WriteBytesExt};
+use std::io::{BufRead, Seek, Write};
+use std::borrow::Cow;
+use std::ffi::CString;
+
+use crate::{DecodeOptions, Endian, FromReader, Result, SagasError, ToWriter};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SagasHeader {
+    pub unk0: u64,
+    pub unk1: u32,
+    pub unk2: u32,
+    pub unk3: u32,
+    pub unk4: u32,
+    #[cfg_attr(feature = "serde", serde(with = "lossy_cstring"))]
+    pub string0: CString, // source file path
+    pub unk5: u32,
+    pub unk6: u32,
+    pub unk7: u32,
+    pub image_offset: u32,
+    pub width: u16,
+    pub height: u16,
+    pub unk9: u32,
+    pub unk10: u32,
+    pub color_table_offset: u32,
+    pub unk12: u16,
+    pub unk13: u16,
+    pub unk14: u32,
+    #[cfg_attr(feature = "serde", serde(with = "lossy_cstring"))]
+    pub string1: CString,
+}
+
+// The embedded strings are serialized as (lossy) UTF-8 strings.
+#[cfg(feature = "serde")]
+mod lossy_cstring {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::ffi::CString;
+
+    pub fn serialize<S: Serializer>(s: &CString, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&s.to_string_lossy())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CString, D::Error> {
+        CString::new(String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+impl<R> FromReader<R> for CString
+    where R : BufRead + Seek
+{
+    fn from_reader(r: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        r.read_until(0, &mut buffer)?;
+        // Without a terminator the stream ended inside the string.
+        if buffer.pop() != Some(0) {
+            return Err(SagasError::Truncated);
+        }
+        Ok(CString::new(buffer)?)
+    }
+}
+
+impl<W> ToWriter<W> for CString
+    where W : Write + Seek
+{
+    fn to_writer(&self, w: &mut W) -> Result<()> {
+        w.write_all(self.as_bytes_with_nul())?;
+        Ok(())
+    }
+}
+
+impl SagasHeader {
+    // Only `options.endian` matters for the header.
+    pub fn from_reader_with<R>(r: &mut R, options: &DecodeOptions) -> Result<Self>
+        where R : BufRead + Seek
+    {
+        match options.endian {
+            Endian::Little => SagasHeader::read_fields::<L, R>(r),
+            Endian::Big => SagasHeader::read_fields::<BigEndian, R>(r),
+        }
+    }
+
+    fn read_fields<B, R>(r: &mut R) -> Result<Self>
+        where B : ByteOrder, R : BufRead + Seek
+    {
+        let unk0 = r.read_u64::<B>()?;
+        let unk1 = r.read_u32::<B>()?;
+        let unk2 = r.read_u32::<B>()?;
+        let unk3 = r.read_u32::<B>()?;
+        let unk4 = r.read_u32::<B>()?;
+        let string0 = CString::from_reader(r)?;
+
+        let unk5 = r.read_u32::<B>()?;
+        let unk6 = r.read_u32::<B>()?;
+        let unk7 = r.read_u32::<B>()?;
+        let image_offset = r.read_u32::<B>()?;
+
+        let width = r.read_u16::<B>()?;
+        let height = r.read_u16::<B>()?;
+
+        let unk9 = r.read_u32::<B>()?;
+        let unk10 = r.read_u32::<B>()?;
+        let color_table_offset = r.read_u32::<B>()?; // offset to beginning of color table
+
+        let unk12 = r.read_u16::<B>()?;
+        let unk13 = r.read_u16::<B>()?;
+        let unk14 = r.read_u32::<B>()?;
+        let string1 = CString::from_reader(r)?;
+
+        Ok(SagasHeader {
+            unk0,
+            unk1,
+            unk2,
+            unk3,
+            unk4,
+            string0,
+            unk5,
+            unk6,
+            unk7,
+            image_offset,
+            width,
+            height,
+            unk9,
+            unk10,
+            color_table_offset,
+            unk12,
+            unk13,
+            unk14,
+            string1,
+        })
+    }
+}
+
+impl<R> FromReader<R> for SagasHeader
+    where R : BufRead + Seek
+{
+    fn from_reader(r: &mut R) -> Result<Self> {
+        SagasHeader::from_reader_with(r, &DecodeOptions::default())
+    }
+}
+
+// Same field order as `from_reader`.
+impl<W> ToWriter<W> for SagasHeader
+    where W : Write + Seek
+{
+    fn to_writer(&self, w: &mut W) -> Result<()> {
+        w.write_u64::<L>(self.unk0)?;
+        w.write_u32::<L>(self.unk1)?;
+        w.write_u32::<L>(self.unk2)?;
+        w.write_u32::<L>(self.unk3)?;
+        w.write_u32::<L>(self.unk4)?;
+        self.string0.to_writer(w)?;
+
+        w.write_u32::<L>(self.unk5)?;
+        w.write_u32::<L>(self.unk6)?;
+        w.write_u32::<L>(self.unk7)?;
+        w.write_u32::<L>(self.image_offset)?;
+
+        w.write_u16::<L>(self.width)?;
+        w.write_u16::<L>(self.height)?;
+
+        w.write_u32::<L>(self.unk9)?;
+        w.write_u32::<L>(self.unk10)?;
+        w.write_u32::<L>(self.color_table_offset)?;
+
+        w.write_u16::<L>(self.unk12)?;
+        w.write_u16::<L>(self.unk13)?;
+        w.write_u32::<L>(self.unk14)?;
+        self.string1.to_writer(w)?;
+        Ok(())
+    }
+}
+
+// Larger than any texture the PS2 could use.
+pub const MAX_PLAUSIBLE_SIZE: u16 = 2048;
+
+// One header field as it was stored, see `SagasHeader::fields`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    pub name: &'static str,
+    pub offset: usize, // from the start of the header
+    pub bytes: Vec<u8>,
+    pub value: String,
+}
+
+impl SagasHeader {
+    // Every field in `from_reader` order, with its offset, the bytes it was
+    // stored as in `endian` order and its decoded value.
+    pub fn fields(&self, endian: Endian) -> Vec<HeaderField> {
+        let int = |v: u64, len: usize| match endian {
+            Endian::Little => v.to_le_bytes()[..len].to_vec(),
+            Endian::Big => v.to_be_bytes()[8 - len..].to_vec(),
+        };
+        let string = |s: &CString| (s.as_bytes_with_nul().to_vec(), format!("{:?}", s.to_string_lossy()));
+        let layout = [
+            ("unk0", int(self.unk0, 8), self.unk0.to_string()),
+            ("unk1", int(self.unk1.into(), 4), self.unk1.to_string()),
+            ("unk2", int(self.unk2.into(), 4), self.unk2.to_string()),
+            ("unk3", int(self.unk3.into(), 4), self.unk3.to_string()),
+            ("unk4", int(self.unk4.into(), 4), self.unk4.to_string()),
+            { let (bytes, value) = string(&self.string0); ("string0", bytes, value) },
+            ("unk5", int(self.unk5.into(), 4), self.unk5.to_string()),
+            ("unk6", int(self.unk6.into(), 4), self.unk6.to_string()),
+            ("unk7", int(self.unk7.into(), 4), self.unk7.to_string()),
+            ("image_offset", int(self.image_offset.into(), 4), format!("{:#x}", self.image_offset)),
+            ("width", int(self.width.into(), 2), self.width.to_string()),
+            ("height", int(self.height.into(), 2), self.height.to_string()),
+            ("unk9", int(self.unk9.into(), 4), self.unk9.to_string()),
+            ("unk10", int(self.unk10.into(), 4), self.unk10.to_string()),
+            ("color_table_offset", int(self.color_table_offset.into(), 4), format!("{:#x}", self.color_table_offset)),
+            ("unk12", int(self.unk12.into(), 2), self.unk12.to_string()),
+            ("unk13", int(self.unk13.into(), 2), self.unk13.to_string()),
+            ("unk14", int(self.unk14.into(), 4), self.unk14.to_string()),
+            { let (bytes, value) = string(&self.string1); ("string1", bytes, value) },
+        ];
+
+        let mut offset = 0;
+        layout.into_iter()
+            .map(|(name, bytes, value)| {
+                let field = HeaderField { name, offset, bytes, value };
+                offset += field.bytes.len();
+                field
+            })
+            .collect()
+    }
+
+    // Path of the asset the image was converted from (`string0`), e.g.
+    // `c:\dev\dbz\data_ps2\sprites\fac`.
+    pub fn source_path(&self) -> Cow<'_, str> {
+        self.string0.to_string_lossy()
+    }
+
+    // Texture name in `string1`, e.g. `Bit08_000.tga`.
+    pub fn secondary_string(&self) -> Cow<'_, str> {
+        self.string1.to_string_lossy()
+    }
+
+    // Size of the header in bytes, which depends on the two strings.
+    pub fn size(&self) -> usize {
+        0x40 + self.string0.as_bytes_with_nul().len() + self.string1.as_bytes_with_nul().len()
+    }
+
+    // Number of palette entries. `unk12` x `unk13` look like the CLUT's
+    // dimensions (16x16 for 8-bit portraits), fall back to 256 otherwise.
+    pub fn palette_size(&self) -> usize {
+        match self.unk12 as usize * self.unk13 as usize {
+            n @ 1..=256 => n,
+            _ => 256,
+        }
+    }
+
+    // Things about the dimensions that suggest the header was misread even
+    // though it parsed: PS2 textures are normally power-of-two sized.
+    pub fn dimension_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (name, size) in [("width", self.width), ("height", self.height)] {
+            if size > MAX_PLAUSIBLE_SIZE {
+                warnings.push(format!("{} {} is larger than {}, the header may be misaligned", name, size, MAX_PLAUSIBLE_SIZE));
+            } else if !size.is_power_of_two() {
+                warnings.push(format!("{} {} is not a power of two", name, size));
+            }
+        }
+        warnings
+    }
+
+    // Bits per pixel of the image data, 4 or 8, or None if the header doesn't
+    // say. The only real sample, data/raditz.dbl, is an 8-bit portrait with
+    // `unk9` and `unk10` both 0, so they don't seem to encode it. It does
+    // show up in two places there, which the 4-bit test fixtures follow:
+    // - `string1` names the source texture with a `BitNN_` prefix
+    //   (`Bit08_000.tga` in raditz.dbl), which is taken first;
+    // - `unk12` x `unk13` is the CLUT, 16x16 in raditz.dbl, and presumably
+    //   8x2 for the 16 colors a 4-bit image can use.
+    pub fn bit_depth(&self) -> Option<u8> {
+        let name = self.string1.to_bytes();
+        match name.strip_prefix(b"Bit").and_then(|rest| rest.get(..2)) {
+            Some(b"04") => return Some(4),
+            Some(b"08") => return Some(8),
+            _ => {},
+        }
+        match self.unk12 as usize * self.unk13 as usize {
+            16 => Some(4),
+            256 => Some(8),
+            _ => None,
+        }
+    }
+
+    // `bit_depth`, or 8 when it can't be determined.
+    pub fn bits_per_pixel(&self) -> u8 {
+        self.bit_depth().unwrap_or(8)
+    }
+}
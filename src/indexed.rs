@@ -0,0 +1,164 @@
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::{Result, SagasColorLUT, SagasError, SagasFile};
+
+// Palette indices laid out as an image, one `Luma` value per pixel, so the
+// `image` crate's flip/crop helpers work on them before any colors are looked up.
+pub type IndexImage = GrayImage;
+
+// How `index_map` stores a palette index in a pixel. Alpha is always 255, so
+// nothing gets dropped or premultiplied on the way to a shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexPacking {
+    #[default]
+    Red,  // R = index, G = B = 0
+    Gray, // R = G = B = index
+}
+
+impl FromStr for IndexPacking {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "red" => Ok(IndexPacking::Red),
+            "gray" | "grey" => Ok(IndexPacking::Gray),
+            _ => Err(format!("unknown index packing '{}' (expected red or gray)", s)),
+        }
+    }
+}
+
+// `indices` as an RGBA image whose pixels hold the palette index instead of
+// its color, for shaders that look the color up in a palette of their own.
+pub fn index_map(indices: &IndexImage, packing: IndexPacking) -> RgbaImage {
+    RgbaImage::from_fn(indices.width(), indices.height(), |x, y| {
+        let Luma([i]) = *indices.get_pixel(x, y);
+        match packing {
+            IndexPacking::Red => Rgba([i, 0, 0, 255]),
+            IndexPacking::Gray => Rgba([i, i, i, 255]),
+        }
+    })
+}
+
+impl SagasColorLUT {
+    fn check_indices(&self, indices: &IndexImage) -> Result<()> {
+        let palette_size = self.colors.len();
+        match indices.iter().find(|&&i| i as usize >= palette_size) {
+            Some(&index) => Err(SagasError::IndexOutOfRange { index, palette_size }),
+            None => Ok(()),
+        }
+    }
+
+    // Writes `indices` as an 8-bit palette PNG, the color table becomes the
+    // PLTE chunk and its alpha the tRNS chunk, so no pixel data is expanded.
+    pub fn write_indexed_png<W: Write>(&self, indices: &IndexImage, w: W) -> Result<()> {
+        self.check_indices(indices)?;
+
+        let mut encoder = png::Encoder::new(w, indices.width(), indices.height());
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.colors.iter().flat_map(|c| [c.r, c.g, c.b]).collect::<Vec<_>>());
+        encoder.set_trns(self.colors.iter().map(|c| c.a).collect::<Vec<_>>());
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(indices.as_raw())?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    // Writes `indices` as a GIF using the table as its global palette. GIF only
+    // has a single transparent index, so the first entry with alpha below 128
+    // is used and partial alpha is lost.
+    pub fn write_indexed_gif<W: Write>(&self, indices: &IndexImage, w: W) -> Result<()> {
+        let (width, height) = gif_dimensions(indices)?;
+        let mut encoder = gif::Encoder::new(w, width, height, &self.gif_palette())?;
+        encoder.write_frame(&self.gif_frame(indices)?)?;
+        Ok(())
+    }
+
+    // Smallest `(x, y, width, height)` rectangle containing every pixel that
+    // isn't fully transparent, or None if all of them are. Indices outside
+    // the table count as visible.
+    pub fn visible_bounds(&self, indices: &IndexImage) -> Option<(u32, u32, u32, u32)> {
+        let visible = |i: u8| self.colors.get(i as usize).is_none_or(|c| c.a > 0);
+        let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+        for (x, y, &Luma([i])) in indices.enumerate_pixels() {
+            if visible(i) {
+                left = left.min(x);
+                top = top.min(y);
+                right = right.max(x);
+                bottom = bottom.max(y);
+            }
+        }
+        (left <= right).then(|| (left, top, right - left + 1, bottom - top + 1))
+    }
+
+    fn gif_palette(&self) -> Vec<u8> {
+        self.colors.iter().flat_map(|c| [c.r, c.g, c.b]).collect()
+    }
+
+    fn gif_frame(&self, indices: &IndexImage) -> Result<gif::Frame<'static>> {
+        self.check_indices(indices)?;
+        let (width, height) = gif_dimensions(indices)?;
+        Ok(gif::Frame {
+            width,
+            height,
+            buffer: indices.as_raw().clone().into(),
+            transparent: self.colors.iter().position(|c| c.a < 128).map(|i| i as u8),
+            ..gif::Frame::default()
+        })
+    }
+}
+
+fn gif_dimensions(indices: &IndexImage) -> Result<(u16, u16)> {
+    let (width, height) = indices.dimensions();
+    match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(SagasError::BadDimensions),
+    }
+}
+
+// Writes `frames` as a looping animated GIF, showing each one for `delay`
+// hundredths of a second. The first frame's palette is the global one, later
+// frames only carry their own when it's different. All frames must have the
+// same size.
+pub fn write_animated_gif<W: Write>(frames: &[(&SagasColorLUT, IndexImage)], delay: u16, w: W) -> Result<()> {
+    let Some((first_lut, first)) = frames.first() else {
+        return Err(SagasError::BadDimensions);
+    };
+    let (width, height) = gif_dimensions(first)?;
+    let global_palette = first_lut.gif_palette();
+
+    let mut encoder = gif::Encoder::new(w, width, height, &global_palette)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for (i, (lut, indices)) in frames.iter().enumerate() {
+        if indices.dimensions() != first.dimensions() {
+            return Err(SagasError::FrameSizeMismatch {
+                frame: i,
+                expected: first.dimensions(),
+                found: indices.dimensions(),
+            });
+        }
+        let mut frame = lut.gif_frame(indices)?;
+        frame.delay = delay;
+        let palette = lut.gif_palette();
+        if palette != global_palette {
+            frame.palette = Some(palette);
+        }
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+impl SagasFile {
+    pub fn index_image(&self) -> IndexImage {
+        let header = self.get_header();
+        IndexImage::from_raw(header.width as u32, header.height as u32, self.get_image().to_vec())
+            .expect("image length is checked when parsing")
+    }
+
+    pub fn write_indexed_png<W: Write>(&self, w: W) -> Result<()> {
+        self.get_color_table().write_indexed_png(&self.index_image(), w)
+    }
+}
@@ -0,0 +1,64 @@
+use std::io::{BufRead, Seek, Write};
+#[cfg(feature = "fs")]
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::RgbaImage;
+
+mod archive;
+mod color;
+mod encode;
+mod error;
+mod file;
+mod header;
+mod indexed;
+mod options;
+mod palette;
+mod pixels;
+mod render;
+mod sheet;
+
+pub use archive::SagasArchive;
+pub use color::{deswizzle_palette, SagasColor, SagasColorLUT};
+pub use encode::encode_rgba;
+pub use error::{Result, SagasError};
+pub use file::{SagasFile, SagasParts};
+pub use header::{HeaderField, SagasHeader, MAX_PLAUSIBLE_SIZE};
+pub use indexed::{index_map, write_animated_gif, IndexImage, IndexPacking};
+pub use options::{AlphaMode, DecodeOptions, Endian};
+pub use palette::PaletteFormat;
+pub use pixels::{pack_4bpp, unpack_4bpp};
+pub use render::{apply_gamma, edge_bleed, flatten, to_planar, to_rgba16, write_png16, Rgba16Image};
+#[cfg(feature = "exr")]
+pub use render::write_linear_exr;
+pub use sheet::{contact_sheet, fit_nearest};
+
+pub trait FromReader<R>
+    where R : BufRead + Seek, Self : Sized
+{
+    fn from_reader(_: &mut R) -> Result<Self>;
+}
+
+pub trait ToWriter<W>
+    where W : Write + Seek
+{
+    fn to_writer(&self, _: &mut W) -> Result<()>;
+}
+
+// Parses a Sagas file held in memory, same as `SagasFile::try_from(data)`.
+pub fn parse_bytes(data: &[u8]) -> Result<SagasFile> {
+    SagasFile::try_from(data)
+}
+
+// Decodes a Sagas file held in memory to its width, height and RGBA pixels,
+// four bytes per pixel without padding. Doesn't touch the file system, so
+// it also builds for wasm32-unknown-unknown (with default features off).
+pub fn decode_to_rgba(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let image = RgbaImage::try_from(&SagasFile::try_from(data)?)?;
+    Ok((image.width(), image.height(), image.into_raw()))
+}
+
+// Opens and parses the Sagas file at `path`.
+#[cfg(feature = "fs")]
+pub fn parse_file(path: impl AsRef<Path>) -> Result<SagasFile> {
+    SagasFile::from_reader(&mut BufReader::new(File::open(path)?))
+}
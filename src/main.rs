@@ -1,252 +1,2502 @@
-use clap::App;
-use byteorder::{LittleEndian as L, ReadBytesExt};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use std::{
-    fs::File,
-    io::{self, Read},
+    collections::{BTreeMap, HashSet},
+    fmt,
+    num::NonZeroU32,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
-use std::io::{Cursor, BufRead, BufReader, Seek, SeekFrom, BufWriter, Result};
-use std::ffi::CString;
-use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use image::{imageops::{self, FilterType}, ImageFormat, ImageOutputFormat, Luma, Rgba, RgbaImage};
 
-pub trait FromReader<R>
-    where R : BufRead + Seek, Self : Sized
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn, Level, LevelFilter};
+use memmap2::Mmap;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use serde_json::json;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use dbz_sagas_extractor::{apply_gamma, contact_sheet, edge_bleed, encode_rgba, fit_nearest, flatten, index_map, parse_file, to_planar, to_rgba16, write_animated_gif, write_png16, AlphaMode, DecodeOptions, Endian, IndexImage, IndexPacking, PaletteFormat, SagasArchive, SagasColor, SagasColorLUT, SagasError, SagasFile, SagasHeader, SagasParts, ToWriter};
+#[cfg(feature = "exr")]
+use dbz_sagas_extractor::write_linear_exr;
+
+// Output path for an input file inside `dir`: <dir>/<input stem>.<extension>
+fn output_path_in(dir: &Path, input: &Path, extension: &str) -> PathBuf {
+    let mut name = input.file_stem().unwrap_or(input.as_os_str()).to_os_string();
+    name.push(".");
+    name.push(extension);
+    dir.join(name)
+}
+
+// Image formats that can be written, parsed from `png`, `bmp`, `tga`, `gif` or `jpg`.
+#[derive(Clone, Copy)]
+struct FormatArg(ImageFormat);
+
+impl FormatArg {
+    fn from_image_format(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Png | ImageFormat::Bmp | ImageFormat::Tga | ImageFormat::Gif | ImageFormat::Jpeg => {
+                Some(FormatArg(format))
+            },
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        self.0.extensions_str()[0]
+    }
+}
+
+impl FromStr for FormatArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ImageFormat::from_extension(s)
+            .and_then(FormatArg::from_image_format)
+            .ok_or_else(|| format!("unsupported format '{}' (expected png, bmp, tga, gif or jpg)", s))
+    }
+}
+
+// Format of an image written to `output`: an explicit --format wins, then the
+// output's extension, then PNG when it has none. EXR, raw planar and index map
+// output don't go by the format, so any extension does for those.
+fn output_format(output: &Path, options: &Options) -> Result<ImageFormat, String> {
+    if options.linear_exr || options.raw_planar || options.index_map.is_some() {
+        return Ok(ImageFormat::Png);
+    }
+    match (options.format, output.extension()) {
+        (Some(FormatArg(format)), _) => Ok(format),
+        (None, Some(extension)) => extension.to_string_lossy().parse().map(|FormatArg(format)| format),
+        (None, None) => Ok(ImageFormat::Png),
+    }
+}
+
+// A `.zip` input, extracted like a directory of its entries.
+fn is_zip_input(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+// `path` with `_r<row>_c<column>` appended to its file stem, for --split-atlas.
+fn cell_path(path: &Path, row: u32, column: u32) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("_r{}_c{}", row, column));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+// `path` with `_<i>` appended to its file stem.
+fn numbered_path(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("_{}", i));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+// File name (without extension) of the asset path embedded in `string0`, or
+// None if it's empty or could escape the output directory.
+fn header_name(header: &SagasHeader) -> Option<String> {
+    let path = header.source_path();
+    let file_name = path.rsplit(['\\', '/']).next().unwrap_or_default();
+    let stem = match file_name.rfind('.') {
+        Some(i) if i > 0 => &file_name[..i],
+        _ => file_name,
+    };
+
+    let unsafe_name = stem.is_empty() || stem == "." || stem == ".."
+        || stem.chars().any(|c| c.is_control() || c == ':');
+    if unsafe_name {
+        None
+    } else {
+        Some(stem.to_string())
+    }
+}
+
+// Which header string --route-by-header groups the outputs by. In the files
+// seen so far `string0` is the asset path the image was converted from, e.g.
+// `c:\dev\dbz\data_ps2\sprites\fac`, and routes by its last directory
+// (`sprites`). `string1` is a texture name like `Bit08_000.tga` and routes by
+// the part before the first `_` (`Bit08`, the bit depth).
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RouteString {
+    String0,
+    String1,
+}
+
+impl FromStr for RouteString {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "string0" => Ok(RouteString::String0),
+            "string1" => Ok(RouteString::String1),
+            _ => Err(format!("unknown header string '{}' (expected string0 or string1)", s)),
+        }
+    }
+}
+
+// Output subdirectory for `header` under --route-by-header, or None if the
+// string doesn't give one. Characters that aren't valid in file names on
+// every platform become `_`, and `.`/`..` are refused so a crafted header
+// can't escape the output directory.
+fn header_route(header: &SagasHeader, string: RouteString) -> Option<String> {
+    let route = match string {
+        RouteString::String0 => {
+            let path = header.source_path();
+            let mut parts = path.split(['\\', '/']).filter(|part| !part.is_empty());
+            parts.next_back();
+            // A bare drive like `c:` isn't a directory worth routing by.
+            parts.next_back().filter(|dir| !dir.ends_with(':')).map(str::to_string)
+        }
+        RouteString::String1 => {
+            let name = header.secondary_string();
+            name.split(['_', '.']).next().map(str::to_string)
+        }
+    }?;
+
+    let route: String = route.trim()
+        .chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    if route.is_empty() || route == "." || route == ".." {
+        None
+    } else {
+        Some(route)
+    }
+}
+
+// A placeholder in an --output-template.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Stem,   // input file name without extension
+    Width,
+    Height,
+    Name,   // `header_name`, or the stem without a usable one
+    Index,  // of the image in a multi-image file, 0 otherwise
+}
+
+enum TemplatePart {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+// Output file name with `{placeholder}`s, parsed (and so checked) once at
+// startup and rendered for every image.
+struct OutputTemplate(Vec<TemplatePart>);
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or_else(|| format!("unclosed '{{' in '{}'", s))? + start;
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            parts.push(TemplatePart::Placeholder(match &rest[start + 1..end] {
+                "stem" => Placeholder::Stem,
+                "width" => Placeholder::Width,
+                "height" => Placeholder::Height,
+                "name" => Placeholder::Name,
+                "index" => Placeholder::Index,
+                other => return Err(format!("unknown placeholder '{{{}}}' (expected stem, width, height, name or index)", other)),
+            }));
+            rest = &rest[end + 1..];
+        }
+        parts.push(TemplatePart::Literal(rest.to_string()));
+        Ok(OutputTemplate(parts))
+    }
+}
+
+impl OutputTemplate {
+    fn uses(&self, placeholder: Placeholder) -> bool {
+        self.0.iter().any(|part| matches!(part, TemplatePart::Placeholder(p) if *p == placeholder))
+    }
+
+    fn render(&self, input: &Path, header: &SagasHeader, index: Option<usize>) -> String {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        self.0.iter()
+            .map(|part| match part {
+                TemplatePart::Literal(literal) => literal.clone(),
+                TemplatePart::Placeholder(Placeholder::Stem) => stem.to_string(),
+                TemplatePart::Placeholder(Placeholder::Width) => header.width.to_string(),
+                TemplatePart::Placeholder(Placeholder::Height) => header.height.to_string(),
+                TemplatePart::Placeholder(Placeholder::Name) => header_name(header).unwrap_or_else(|| stem.to_string()),
+                TemplatePart::Placeholder(Placeholder::Index) => index.unwrap_or(0).to_string(),
+            })
+            .collect()
+    }
+}
+
+fn header_json(header: &SagasHeader) -> serde_json::Value {
+    json!({
+        "unk0": header.unk0,
+        "unk1": header.unk1,
+        "unk2": header.unk2,
+        "unk3": header.unk3,
+        "unk4": header.unk4,
+        "string0": header.source_path(),
+        "unk5": header.unk5,
+        "unk6": header.unk6,
+        "unk7": header.unk7,
+        "image_offset": header.image_offset,
+        "width": header.width,
+        "height": header.height,
+        "unk9": header.unk9,
+        "unk10": header.unk10,
+        "color_table_offset": header.color_table_offset,
+        "unk12": header.unk12,
+        "unk13": header.unk13,
+        "unk14": header.unk14,
+        "string1": header.secondary_string(),
+    })
+}
+
+// Hexdump of the header with one field per line, e.g. `0x0048  80 00  width=128`.
+// Fields longer than 16 bytes (the strings) continue on the following lines.
+fn annotated_header(header: &SagasHeader, endian: Endian) -> String {
+    let mut lines = Vec::new();
+    for field in header.fields(endian) {
+        for (i, chunk) in field.bytes.chunks(16).enumerate() {
+            let hex = chunk.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+            let label = if i == 0 { format!("{}={}", field.name, field.value) } else { String::new() };
+            lines.push(format!("{:#06x}  {:<47}  {}", field.offset + 16 * i, hex, label).trim_end().to_string());
+        }
+    }
+    lines.join("\n")
+}
+
+// How often each palette index occurs in `indices`, as a single line of JSON
+// so results for many files can be collected one per line.
+fn index_histogram_json(input: &Path, image: Option<usize>, indices: &IndexImage) -> serde_json::Value {
+    let mut counts = [0u64; 256];
+    for &Luma([i]) in indices.pixels() {
+        counts[i as usize] += 1;
+    }
+    let used: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] > 0).collect();
+    let histogram: serde_json::Map<String, serde_json::Value> = used.iter()
+        .map(|&i| (i.to_string(), counts[i].into()))
+        .collect();
+
+    json!({
+        "file": input.to_string_lossy(),
+        "image": image,
+        "distinct": used.len(),
+        "min": used.first(),
+        "max": used.last(),
+        "counts": histogram,
+    })
+}
+
+// How many pixels of `indices` have a stored alpha of 0, 1-127, 128, 129-254
+// and 255, as a single line of JSON like `index_histogram_json`. The palette
+// has to be decoded with `AlphaMode::Raw`. PS2 alpha tops out at 128, so any
+// pixel above it means the file wants --alpha-mode raw rather than double.
+// Pixels whose index is past the palette have no stored alpha, they're
+// counted on their own.
+fn alpha_histogram_json(input: &Path, image: Option<usize>, indices: &IndexImage, lut: &SagasColorLUT)
+    -> serde_json::Value
+{
+    let (mut counts, mut out_of_range) = ([0u64; 5], 0u64);
+    for &Luma([i]) in indices.pixels() {
+        let Some(c) = lut.colors.get(i as usize) else {
+            out_of_range += 1;
+            continue;
+        };
+        let bucket = match c.a {
+            0 => 0,
+            1..=127 => 1,
+            128 => 2,
+            129..=254 => 3,
+            255 => 4,
+        };
+        counts[bucket] += 1;
+    }
+    let visible = counts[1..].iter().sum::<u64>();
+    let suggested = match (visible, counts[3] + counts[4]) {
+        (0, _) => None,
+        (_, 0) => Some("double"),
+        _ => Some("raw"),
+    };
+
+    json!({
+        "file": input.to_string_lossy(),
+        "image": image,
+        "pixels": counts.iter().sum::<u64>() + out_of_range,
+        "counts": {
+            "0": counts[0],
+            "1-127": counts[1],
+            "128": counts[2],
+            "129-254": counts[3],
+            "255": counts[4],
+        },
+        "out_of_range": out_of_range,
+        "suggested_alpha_mode": suggested,
+    })
+}
+
+// Image size, parsed from `WxH`.
+struct Dimensions(u16, u16);
+
+impl FromStr for Dimensions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(|| format!("expected WxH but got '{}'", s))?;
+        match (width.trim().parse::<u16>(), height.trim().parse::<u16>()) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok(Dimensions(width, height)),
+            (Ok(_), Ok(_)) => Err("width and height must be non-zero".to_string()),
+            (Err(e), _) | (_, Err(e)) => Err(e.to_string()),
+        }
+    }
+}
+
+// Byte offset, decimal or hex with a `0x` prefix.
+struct OffsetArg(u32);
+
+impl FromStr for OffsetArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        parsed.map(OffsetArg).map_err(|e| e.to_string())
+    }
+}
+
+// Sub-rectangle of the image, parsed from `X,Y,W,H`.
+#[derive(Clone, Copy)]
+struct Crop {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s.split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Crop { x, y, width, height }),
+            [_, _, _, _] => Err("width and height must be non-zero".to_string()),
+            _ => Err(format!("expected X,Y,W,H but got '{}'", s)),
+        }
+    }
+}
+
+impl Crop {
+    fn apply(&self, indices: &IndexImage) -> Result<IndexImage, SagasError> {
+        let (width, height) = indices.dimensions();
+        let fits = self.x.checked_add(self.width).is_some_and(|right| right <= width)
+            && self.y.checked_add(self.height).is_some_and(|bottom| bottom <= height);
+        if !fits {
+            let Crop { x, y, width, height } = *self;
+            return Err(SagasError::CropOutOfBounds { x, y, width, height });
+        }
+        Ok(imageops::crop_imm(indices, self.x, self.y, self.width, self.height).to_image())
+    }
+}
+
+// --split-atlas: the image is a grid of `columns` x `rows` equally sized sprites.
+#[derive(Clone, Copy)]
+struct Atlas {
+    columns: u32,
+    rows: u32,
+}
+
+impl FromStr for Atlas {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s.split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        match values[..] {
+            [columns, rows] if columns > 0 && rows > 0 => Ok(Atlas { columns, rows }),
+            [_, _] => Err("columns and rows must be non-zero".to_string()),
+            _ => Err(format!("expected COLS,ROWS but got '{}'", s)),
+        }
+    }
+}
+
+impl Atlas {
+    // The row, column and rectangle of every cell of a `width` x `height`
+    // image, row by row.
+    fn cells(&self, width: u32, height: u32) -> Result<Vec<(u32, u32, Crop)>, SagasError> {
+        let Atlas { columns, rows } = *self;
+        if !width.is_multiple_of(columns) || !height.is_multiple_of(rows) {
+            return Err(SagasError::UnevenAtlas { width, height, columns, rows });
+        }
+        let (cell_width, cell_height) = (width / columns, height / rows);
+        Ok((0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (row, column)))
+            .map(|(row, column)| {
+                let crop = Crop { x: column * cell_width, y: row * cell_height, width: cell_width, height: cell_height };
+                (row, column, crop)
+            })
+            .collect())
+    }
+}
+
+// Color given as `#RRGGBB` or `#RRGGBBAA`, like `SagasColor` parses it, or
+// as the older `R,G,B` or `R,G,B,A`. Either way alpha defaults to opaque.
+#[derive(Clone, Copy)]
+struct ColorArg(SagasColor);
+
+impl ColorArg {
+    fn rgb(&self) -> [u8; 3] {
+        [self.0.r, self.0.g, self.0.b]
+    }
+}
+
+impl FromStr for ColorArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(',') {
+            return s.parse().map(ColorArg);
+        }
+        let values = s.split(',')
+            .map(|v| v.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        match values[..] {
+            [r, g, b] => Ok(ColorArg(SagasColor { r, g, b, a: 255 })),
+            [r, g, b, a] => Ok(ColorArg(SagasColor { r, g, b, a })),
+            _ => Err(format!("expected #RRGGBB, #RRGGBBAA, R,G,B or R,G,B,A but got '{}'", s)),
+        }
+    }
+}
+
+struct Options {
+    format: Option<FormatArg>,
+    verbose: bool,
+    quiet: bool,
+    recursive: bool,
+    select: Globs,
+    exclude: Globs,
+    jobs: usize,
+    decode: DecodeOptions,
+    dump_header: bool,
+    annotate: bool,
+    export_palette: Option<PaletteFormat>,
+    indexed: bool,
+    palette_image: bool,
+    palette_only: bool,
+    sort_palette: bool,
+    name_from_header: bool,
+    output_template: Option<OutputTemplate>,
+    route_by_header: Option<RouteString>,
+    transparent_index: Option<u8>,
+    transparent_color: Option<ColorArg>,
+    crop: Option<Crop>,
+    split_atlas: Option<Atlas>,
+    flip_vertical: bool,
+    flip_horizontal: bool,
+    scale: Option<NonZeroU32>,
+    premultiply: bool,
+    index_histogram: bool,
+    alpha_histogram: bool,
+    no_clobber: bool,
+    background: ColorArg,
+    dry_run: bool,
+    strings: bool,
+    manifest: Option<PathBuf>,
+    json_summary: Option<PathBuf>,
+    contact_sheet: Option<PathBuf>,
+    sheet_columns: u32,
+    cell_size: u32,
+    sheet_labels: bool,
+    gamma: f32,
+    alpha_scale: f32,
+    alpha_offset: i32,
+    depth16: bool,
+    used_outputs: Mutex<HashSet<PathBuf>>, // claimed so far this run, shared by the workers
+    timings: Option<Mutex<Vec<Timing>>>, // with --timings, one per extracted input
+    zip: Option<ZipOutput>,
+    limit: Option<usize>,
+    linear_exr: bool,
+    raw_planar: bool,
+    index_map: Option<IndexPacking>,
+    hash: bool,
+    dump_indices: Option<PathBuf>,
+    edge_bleed: Option<u32>,
+    palette_from: Option<SagasColorLUT>,
+    strip_alpha: bool,
+    animate: bool,
+    fps: f32,
+    strict: bool,
+    oob_color: ColorArg,
+    autocrop: bool,
+    autocrop_padding: u32,
+}
+
+// Opaque magenta, for pixels whose index is past the end of the palette.
+const DEFAULT_OOB_COLOR: ColorArg = ColorArg(SagasColor { r: 255, g: 0, b: 255, a: 255 });
+
+// Magenta, so pixels that were transparent are easy to spot.
+const DEFAULT_BACKGROUND: ColorArg = ColorArg(SagasColor { r: 255, g: 0, b: 255, a: 255 });
+
+// The color with red, green and blue multiplied by alpha, rounded to nearest.
+fn premultiplied(Rgba([r, g, b, a]): Rgba<u8>) -> Rgba<u8> {
+    let scale = |v: u8| ((v as u16 * a as u16 + 127) / 255) as u8;
+    Rgba([scale(r), scale(g), scale(b), a])
+}
+
+// Scaled images bigger than this on either side are probably a typo.
+const MAX_SCALED_SIZE: u32 = 16384;
+
+// Inputs at least this big are memory-mapped instead of read through a BufReader.
+const MMAP_THRESHOLD: u64 = 1 << 20;
+
+// Zip entries bigger than this uncompressed aren't read, far more than any
+// portrait (or archive of them) needs.
+const MAX_ZIP_ENTRY_SIZE: u64 = 64 << 20;
+
+// `-` as an input or output path means stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+impl Options {
+    // GIF frame delay for --fps, in hundredths of a second.
+    fn frame_delay(&self) -> u16 {
+        (100.0 / self.fps).round().clamp(1.0, u16::MAX as f32) as u16
+    }
+
+    // Whether information is printed instead of extracting images.
+    fn report_only(&self) -> bool {
+        self.dump_header || self.annotate || self.index_histogram || self.alpha_histogram || self.strings || self.hash
+    }
+
+    // Extension for output files whose name isn't given explicitly.
+    fn extension(&self) -> &'static str {
+        if self.linear_exr {
+            return "exr";
+        }
+        if self.raw_planar {
+            return "raw";
+        }
+        self.format.map_or("png", |format| format.extension())
+    }
+}
+
+// What happened to one image of an input.
+enum Extracted {
+    Written(Output),
+    Reported, // only information was printed
+    Skipped(PathBuf), // the output already existed and --no-clobber was given
+}
+
+impl Extracted {
+    fn written(&self) -> Option<&Output> {
+        match self {
+            Extracted::Written(output) => Some(output),
+            _ => None,
+        }
+    }
+}
+
+// Logs one line per image of `input` that was written or skipped.
+fn report(input: &Path, extracted: &[Extracted]) {
+    for e in extracted {
+        match e {
+            Extracted::Written(output) => info!("Extracted {} to {}", input.display(), output.path.display()),
+            Extracted::Skipped(path) => info!("Skipped {}, {} already exists", input.display(), path.display()),
+            Extracted::Reported => {},
+        }
+    }
+}
+
+// Whether every image of an input was skipped rather than written. Inputs
+// count as skipped only then, as opposed to succeeded.
+fn all_skipped(extracted: &[Extracted]) -> bool {
+    !extracted.is_empty() && extracted.iter().all(|e| matches!(e, Extracted::Skipped(_)))
+}
+
+// Writes a JSON report of the whole run to `path`: the per input counts from
+// the summary line, the errors of failed inputs and the bytes written.
+// `not_processed` inputs were left out because of --limit.
+fn write_json_summary(path: &Path, results: &[(PathBuf, Result<Vec<Extracted>, SagasError>)], not_processed: usize)
+    -> io::Result<()>
+{
+    let (mut succeeded, mut skipped, mut bytes_written) = (0, 0, 0);
+    let mut errors = Vec::new();
+    for (input, result) in results {
+        match result {
+            Ok(images) if all_skipped(images) => skipped += 1,
+            Ok(images) => {
+                succeeded += 1;
+                bytes_written += images.iter().filter_map(Extracted::written).map(|o| o.bytes_written).sum::<u64>();
+            },
+            Err(e) => errors.push(json!({ "input": input.display().to_string(), "error": e.to_string() })),
+        }
+    }
+    let summary = json!({
+        "total": results.len(),
+        "succeeded": succeeded,
+        "skipped": skipped,
+        "failed": errors.len(),
+        "errors": errors,
+        "bytes_written": bytes_written,
+        "truncated": not_processed > 0,
+        "not_processed": not_processed,
+    });
+    fs::write(path, serde_json::to_string_pretty(&summary)? + "\n")
+}
+
+// Tiles the thumbnail of every written image into one PNG at `path`, in
+// input order and labeled with the output's file name.
+fn write_contact_sheet(path: &Path, results: &[(PathBuf, Result<Vec<Extracted>, SagasError>)], options: &Options)
+    -> Result<(), SagasError>
 {
-    fn from_reader(_: &mut R) -> Result<Self>;
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct SagasColor {
-    r: u8,
-    g: u8,
-    b: u8,
-    a: u8,
-}
-
-#[derive(Debug)]
-struct SagasColorLUT {
-    colors: Vec<SagasColor>,
-}
-
-#[derive(Debug)]
-struct SagasHeader {
-    unk0: u64,
-    unk1: u32,
-    unk2: u32,
-    unk3: u32,
-    unk4: u32,
-    string0: CString, // source file path
-    unk5: u32,
-    unk6: u32,
-    unk7: u32,
-    image_offset: u32,
+    let cells: Vec<_> = results.iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+        .flatten()
+        .filter_map(Extracted::written)
+        .filter_map(|output| {
+            let label = output.path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            output.thumbnail.clone().map(|thumbnail| (thumbnail, label))
+        })
+        .collect();
+    if cells.is_empty() {
+        warn!("No images were written, not creating contact sheet {}.", path.display());
+        return Ok(());
+    }
+    create_parent_dir(path)?;
+    contact_sheet(&cells, options.sheet_columns, options.cell_size, options.sheet_labels).save(path)?;
+    Ok(())
+}
+
+// An image that was written, as recorded in the manifest.
+struct Output {
+    input: PathBuf,
+    path: PathBuf,
     width: u16,
     height: u16,
-    unk9: u32,
-    unk10: u32,
-    color_table_offset: u32,
-    unk12: u16,
-    unk13: u16,
-    unk14: u32,
-    string1: CString,
+    palette_size: usize,
+    source_path: String,
+    index_hash: u64,
+    offset: (u32, u32), // of the written image inside the decoded one, after cropping
+    bytes_written: u64, // by the image and the files written next to it
+    thumbnail: Option<RgbaImage>, // for --contact-sheet, already fitted to a cell
+}
+
+// Appends a row per output to the CSV file at `path`, starting it with a
+// header row if it's new.
+fn append_manifest<'a>(path: &Path, outputs: impl IntoIterator<Item = &'a Output>) -> Result<(), SagasError> {
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    let is_new = file.metadata()?.len() == 0;
+    let mut w = csv::Writer::from_writer(BufWriter::new(file));
+
+    if is_new {
+        w.write_record(["input", "output", "width", "height", "palette_size", "string0", "index_hash",
+                        "offset_x", "offset_y"])
+            .map_err(io::Error::other)?;
+    }
+    for output in outputs {
+        w.write_record([
+            output.input.to_string_lossy().as_ref(),
+            output.path.to_string_lossy().as_ref(),
+            &output.width.to_string(),
+            &output.height.to_string(),
+            &output.palette_size.to_string(),
+            &output.source_path,
+            &format!("{:016x}", output.index_hash),
+            &output.offset.0.to_string(),
+            &output.offset.1.to_string(),
+        ]).map_err(io::Error::other)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+// Creates the directory `path` goes in, if it doesn't exist yet.
+fn create_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) => fs::create_dir_all(parent).map_err(|e| {
+            io::Error::new(e.kind(), format!("could not create {}: {}", parent.display(), e))
+        }),
+        None => Ok(()),
+    }
+}
+
+// Reserves `path` for `input`. If another input of this run already got it
+// (say two headers with the same source name), `_1`, `_2`, ... is appended to
+// the file stem until the path is free.
+fn claim_output(path: &Path, input: &Path, options: &Options) -> PathBuf {
+    let mut used = options.used_outputs.lock().unwrap_or_else(|e| e.into_inner());
+    let mut claimed = path.to_path_buf();
+    for i in 1.. {
+        if used.insert(claimed.clone()) {
+            break;
+        }
+        claimed = numbered_path(path, i);
+    }
+    if claimed != path {
+        warn!("{}: {} is already used by another input, writing {} instead.",
+              input.display(), path.display(), claimed.display());
+    }
+    claimed
+}
+
+// --zip: outputs go into one archive instead of loose files, named by their
+// path relative to the output directory. The workers take turns adding them.
+struct ZipOutput {
+    root: PathBuf,
+    writer: Mutex<Option<ZipWriter<BufWriter<File>>>>, // None once finished
+}
+
+impl ZipOutput {
+    fn create(path: &Path, root: &Path) -> io::Result<Self> {
+        create_parent_dir(path)?;
+        let writer = ZipWriter::new(BufWriter::new(File::create(path)?));
+        Ok(ZipOutput { root: root.to_path_buf(), writer: Mutex::new(Some(writer)) })
+    }
+
+    fn add(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let name = relative.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        // PNGs are compressed already.
+        let entry = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let zip = writer.as_mut().ok_or_else(|| io::Error::other("zip archive is already finished"))?;
+        zip.start_file(name, entry)?;
+        zip.write_all(data)
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        match writer.take() {
+            Some(zip) => zip.finish()?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Writes `data` to `path`, creating its directory first, or adds it to the
+// --zip archive. With --no-clobber an existing file is left alone and false
+// is returned, `create_new` makes the check and the create one step.
+fn write_output(path: &Path, data: &[u8], options: &Options) -> io::Result<bool> {
+    if let Some(zip) = &options.zip {
+        zip.add(path, data)?;
+        return Ok(true);
+    }
+
+    create_parent_dir(path)?;
+    let file = if options.no_clobber {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    } else {
+        File::create(path)?
+    };
+    let mut w = BufWriter::new(file);
+    w.write_all(data)?;
+    w.flush()?;
+    Ok(true)
+}
+
+fn extract(input: &Path, output: &Path, options: &Options) -> Result<Vec<Extracted>, SagasError> {
+    if is_stdio(input) {
+        // The parser needs to seek, so buffer all of stdin first.
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        extract_from(&mut Cursor::new(data), input, output, options)
+    } else {
+        let file = File::open(input)?;
+        if file.metadata()?.len() >= MMAP_THRESHOLD {
+            // SAFETY: the map is only read while parsing, and nothing in this
+            // process writes to the input. Another process truncating the file
+            // underneath us is the usual mmap caveat we accept for speed.
+            let map = unsafe { Mmap::map(&file)? };
+            extract_from(&mut Cursor::new(&map[..]), input, output, options)
+        } else {
+            extract_from(&mut BufReader::new(file), input, output, options)
+        }
+    }
 }
 
-#[derive(Debug)]
-struct SagasFile {
-    header: SagasHeader,
-    lut: SagasColorLUT,
-    image: Vec<u8>,
+// Writes every image of an archive as the frames of one animated GIF.
+fn extract_animation(mut files: Vec<SagasFile>, input: &Path, output: &Path, options: &Options)
+    -> Result<Extracted, SagasError>
+{
+    // Frames have to stay the same size, which trimming each one would break.
+    if options.autocrop {
+        warn!("{}: --autocrop doesn't apply to animations.", input.display());
+    }
+
+    let mut frames = Vec::with_capacity(files.len());
+    for sf in &mut files {
+        apply_palette_options(sf, input, options)?;
+        frames.push(scale(crop_and_flip(sf, sf.index_image(), input, options, options.crop, false)?.0, input, options));
+    }
+    let frames: Vec<_> = files.iter().map(SagasFile::get_color_table).zip(frames).collect();
+
+    let output = if is_stdio(output) { output.to_path_buf() } else { claim_output(&output.with_extension("gif"), input, options) };
+    if options.dry_run {
+        let (width, height) = frames[0].1.dimensions();
+        println!("{} -> {} ({} frames, {}x{})", input.display(), output.display(), frames.len(), width, height);
+        return Ok(Extracted::Reported);
+    }
+
+    let mut encoded = Vec::new();
+    write_animated_gif(&frames, options.frame_delay(), &mut encoded)?;
+    if is_stdio(&output) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&encoded)?;
+        stdout.flush()?;
+    } else {
+        if !write_output(&output, &encoded, options)? {
+            return Ok(Extracted::Skipped(output));
+        }
+    }
+
+    // The manifest describes the animation by its first frame.
+    let first = &files[0];
+    let header = first.get_header();
+    Ok(Extracted::Written(Output {
+        input: input.to_path_buf(),
+        path: output,
+        width: header.width,
+        height: header.height,
+        palette_size: first.get_color_table().colors.len(),
+        source_path: header.source_path().into_owned(),
+        index_hash: first.index_hash(),
+        offset: options.crop.map_or((0, 0), |crop| (crop.x, crop.y)),
+        bytes_written: encoded.len() as u64,
+        thumbnail: None,
+    }))
+}
+
+fn extract_from<R>(r: &mut R, input: &Path, output: &Path, options: &Options) -> Result<Vec<Extracted>, SagasError>
+    where R : BufRead + Seek
+{
+    if options.palette_only {
+        return extract_palette(r, input, output, options);
+    }
+    let started = Instant::now();
+    let mut files = SagasArchive::from_reader_with(r, &options.decode)?.files;
+    let decode = started.elapsed();
+
+    let extracted = if options.animate && files.len() > 1 && !options.report_only() {
+        vec![extract_animation(files, input, output, options)?]
+    } else {
+        if files.len() > 1 && is_stdio(output) && !options.report_only() {
+            warn!("{} contains {} images, only the first is written to stdout.",
+                  input.display(), files.len());
+            files.truncate(1);
+        }
+
+        // Archives get one output per image, numbered in the order they're stored.
+        let numbered = files.len() > 1;
+        files.into_iter()
+            .enumerate()
+            .map(|(i, sf)| extract_file(sf, input, output, numbered.then_some(i), options))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect()
+    };
+
+    if let Some(timings) = &options.timings {
+        let save = started.elapsed() - decode;
+        timings.lock().unwrap_or_else(|e| e.into_inner()).push(Timing { input: input.to_path_buf(), decode, save });
+    }
+    Ok(extracted)
+}
+
+// How long one input took to parse, and to render, encode and write all of
+// its images, for --timings.
+struct Timing {
+    input: PathBuf,
+    decode: Duration,
+    save: Duration,
+}
+
+// Prints the --timings of every input in input order, then the minimum,
+// maximum, mean and 95th percentile of both over the whole run.
+fn print_timings(options: &Options) {
+    let Some(timings) = &options.timings else {
+        return;
+    };
+    let mut timings = timings.lock().unwrap_or_else(|e| e.into_inner());
+    if timings.is_empty() {
+        return;
+    }
+    timings.sort_by(|a, b| a.input.cmp(&b.input));
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    for t in timings.iter() {
+        eprintln!("timing: {}: decode {:.2} ms, save {:.2} ms", t.input.display(), ms(t.decode), ms(t.save));
+    }
+    let stats = |mut values: Vec<Duration>| {
+        values.sort();
+        let mean = values.iter().sum::<Duration>() / values.len() as u32;
+        // Nearest rank, so with few inputs this is simply the slowest.
+        let p95 = values[(values.len() * 95).div_ceil(100) - 1];
+        format!("min {:.2} ms, max {:.2} ms, mean {:.2} ms, p95 {:.2} ms",
+                ms(values[0]), ms(values[values.len() - 1]), ms(mean), ms(p95))
+    };
+    eprintln!("timing: decode {}", stats(timings.iter().map(|t| t.decode).collect()));
+    eprintln!("timing: save {}", stats(timings.iter().map(|t| t.save).collect()));
+}
+
+// Replaces or edits the palette as asked for on the command line.
+fn apply_palette_options(sf: &mut SagasFile, input: &Path, options: &Options) -> Result<(), SagasError> {
+    if let Some(palette) = &options.palette_from {
+        // The palette was picked for this image, so unlike a damaged file
+        // it has to cover every index, --strict or not.
+        let lut_size = palette.colors.len();
+        if let Some(&index) = sf.get_image().iter().find(|&&i| i as usize >= lut_size) {
+            return Err(SagasError::BadPalette(format!(
+                "--palette-from has {} colors but the image uses index {}", lut_size, index)));
+        }
+        // Only the colors are replaced, alpha stays what the file had.
+        let lut = sf.get_color_table_mut();
+        let mut colors = palette.colors.clone();
+        for (new, old) in colors.iter_mut().zip(&lut.colors) {
+            new.a = old.a;
+        }
+        lut.colors = colors;
+    }
+    if let Some(index) = options.transparent_index {
+        sf.get_color_table_mut().set_transparent_index(index)?;
+    }
+    if let Some(color) = options.transparent_color {
+        sf.get_color_table_mut().set_transparent_color(color.rgb());
+    }
+    // On top of whatever --alpha-mode decoded.
+    if options.alpha_scale != 1.0 || options.alpha_offset != 0 {
+        sf.get_color_table_mut().remap_alpha(options.alpha_scale, options.alpha_offset);
+    }
+
+    // Indices past the end of the palette get --oob-color. Padding the
+    // palette rather than patching pixels keeps indexed output valid too.
+    if let Err(e @ SagasError::IndexOutOfRange { .. }) = sf.check_indices() {
+        if options.strict {
+            return Err(e);
+        }
+        // --alpha-histogram counts them without making up a color.
+        if !options.alpha_histogram {
+            let palette_size = sf.get_color_table().colors.len();
+            let max = *sf.get_image().iter().max().unwrap_or(&0) as usize;
+            let count = sf.get_image().iter().filter(|&&i| i as usize >= palette_size).count();
+            warn!("{}: {} pixels use indices past the {} color palette, drawing them as {}.",
+                  input.display(), count, palette_size, options.oob_color.0);
+            sf.get_color_table_mut().colors.resize(max + 1, options.oob_color.0);
+        }
+    }
+
+    // Done last, on the final palette rather than while decoding, so it also
+    // covers --palette-from, the alpha remapping, the --oob-color padding and
+    // every output format.
+    if options.strip_alpha {
+        for c in &mut sf.get_color_table_mut().colors {
+            c.a = 255;
+        }
+    }
+    Ok(())
+}
+
+// `indices`, the image of `sf`, after cropping to `region` (--crop or an
+// atlas cell), --autocrop and the flips, and where the result's top left
+// corner was in the decoded image.
+fn crop_and_flip(sf: &SagasFile, mut indices: IndexImage, input: &Path, options: &Options, region: Option<Crop>,
+                 autocrop: bool) -> Result<(IndexImage, (u32, u32)), SagasError>
+{
+    let mut offset = (0, 0);
+    if let Some(crop) = region {
+        indices = crop.apply(&indices)?;
+        offset = (crop.x, crop.y);
+    }
+    // Before flipping, so the offset is in the decoded image's coordinates.
+    if autocrop {
+        match sf.get_color_table().visible_bounds(&indices) {
+            Some((x, y, width, height)) => {
+                let padding = options.autocrop_padding;
+                let (left, top) = (x.saturating_sub(padding), y.saturating_sub(padding));
+                let right = (x + width).saturating_add(padding).min(indices.width());
+                let bottom = (y + height).saturating_add(padding).min(indices.height());
+                indices = imageops::crop_imm(&indices, left, top, right - left, bottom - top).to_image();
+                offset = (offset.0 + left, offset.1 + top);
+            },
+            None => warn!("{}: every pixel is transparent, not cropping.", input.display()),
+        }
+    }
+    if options.flip_vertical {
+        imageops::flip_vertical_in_place(&mut indices);
+    }
+    if options.flip_horizontal {
+        imageops::flip_horizontal_in_place(&mut indices);
+    }
+    Ok((indices, offset))
+}
+
+// Applies --scale. Scaling the indices keeps every output format in sync, and
+// nearest neighbour never introduces colors that aren't in the palette.
+fn scale(indices: IndexImage, input: &Path, options: &Options) -> IndexImage {
+    let Some(scale) = options.scale else {
+        return indices;
+    };
+    let (width, height) = indices.dimensions();
+    let (width, height) = (width.saturating_mul(scale.get()), height.saturating_mul(scale.get()));
+    if width > MAX_SCALED_SIZE || height > MAX_SCALED_SIZE {
+        warn!("{}: scaling to {}x{}, this may use a lot of memory.", input.display(), width, height);
+    }
+    imageops::resize(&indices, width, height, FilterType::Nearest)
+}
+
+fn extract_file(mut sf: SagasFile, input: &Path, output: &Path, index: Option<usize>, options: &Options)
+    -> Result<Vec<Extracted>, SagasError>
+{
+    apply_palette_options(&mut sf, input, options)?;
+
+    if options.dump_header {
+        println!("{}", serde_json::to_string_pretty(&header_json(sf.get_header())).unwrap());
+        return Ok(vec![Extracted::Reported]);
+    }
+
+    if options.annotate {
+        match index {
+            Some(i) => println!("{}#{}", input.display(), i),
+            None => println!("{}", input.display()),
+        }
+        println!("{}", annotated_header(sf.get_header(), options.decode.endian));
+        return Ok(vec![Extracted::Reported]);
+    }
+
+    debug!("{:#?}", sf);
+    if sf.get_header().bit_depth().is_none() && options.decode.bits_per_pixel.is_none() {
+        warn!("{}: the header doesn't give the bit depth, assuming 8 (use --bpp to override).", input.display());
+    }
+    for warning in sf.get_header().dimension_warnings() {
+        warn!("{}: {}.", input.display(), warning);
+    }
+
+    let to_stdout = is_stdio(output);
+    let mut output = output.to_path_buf();
+    if options.name_from_header && !to_stdout {
+        match header_name(sf.get_header()) {
+            Some(name) => output.set_file_name(format!("{}.{}", name, options.extension())),
+            None => warn!("{}: header has no usable source name, writing {} instead.",
+                          input.display(), output.display()),
+        }
+    }
+    if let Some(template) = options.output_template.as_ref().filter(|_| !to_stdout) {
+        output = output.with_file_name(template.render(input, sf.get_header(), index));
+    }
+    if let Some(string) = options.route_by_header.filter(|_| !to_stdout) {
+        match header_route(sf.get_header(), string) {
+            Some(route) => {
+                let file_name = output.file_name().unwrap_or_default().to_os_string();
+                output = output.with_file_name(route).join(file_name);
+            }
+            None => warn!("{}: header string gives no usable subdirectory, writing to {} instead.",
+                          input.display(), output.display()),
+        }
+    }
+    // A template with {index} already tells the images apart.
+    let indexed_by_template = options.output_template.as_ref().is_some_and(|t| t.uses(Placeholder::Index));
+    if let Some(i) = index.filter(|_| !to_stdout && !indexed_by_template) {
+        output = numbered_path(&output, i);
+    }
+    if !to_stdout && !options.report_only() && options.split_atlas.is_none() {
+        output = claim_output(&output, input, options);
+    }
+    let output = output.as_path();
+
+    if options.hash {
+        match index {
+            Some(i) => println!("{:016x}  {}#{}", sf.content_hash(), input.display(), i),
+            None => println!("{:016x}  {}", sf.content_hash(), input.display()),
+        }
+        return Ok(vec![Extracted::Reported]);
+    }
+
+    if options.strings {
+        let header = sf.get_header();
+        println!("{}\t{}\t{}", output.display(), header.source_path(), header.secondary_string());
+        return Ok(vec![Extracted::Reported]);
+    }
+
+    // Every cell is cropped, flipped and written as if it were its own image.
+    // The palette is the same for all of them, so it's written once, named
+    // after the whole sheet.
+    if let Some(atlas) = options.split_atlas.filter(|_| !options.report_only()) {
+        let sheet = sf.index_image();
+        let mut extracted = atlas.cells(sheet.width(), sheet.height())?
+            .into_iter()
+            .map(|(row, column, cell)| {
+                let (indices, offset) = crop_and_flip(&sf, sheet.clone(), input, options, Some(cell), options.autocrop)?;
+                let output = claim_output(&cell_path(output, row, column), input, options);
+                write_image(&sf, indices, offset, input, &output, index, options)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let first = extracted.iter_mut().find_map(|e| match e {
+            Extracted::Written(output) => Some(output),
+            _ => None,
+        });
+        if let Some(first) = first {
+            first.bytes_written += write_palette_outputs(sf.get_color_table(), output, options)?;
+        }
+        return Ok(extracted);
+    }
+
+    let (indices, offset) = crop_and_flip(&sf, sf.index_image(), input, options, options.crop, options.autocrop)?;
+    // Counted before scaling so the numbers match the file's own pixels.
+    if options.index_histogram {
+        println!("{}", index_histogram_json(input, index, &indices));
+        return Ok(vec![Extracted::Reported]);
+    }
+    if options.alpha_histogram {
+        println!("{}", alpha_histogram_json(input, index, &indices, sf.get_color_table()));
+        return Ok(vec![Extracted::Reported]);
+    }
+    let mut extracted = write_image(&sf, indices, offset, input, output, index, options)?;
+    if let Extracted::Written(written) = &mut extracted {
+        written.bytes_written += write_palette_outputs(sf.get_color_table(), output, options)?;
+    }
+    Ok(vec![extracted])
+}
+
+// Scales `indices`, the part of `sf` at `offset`, and writes them to `output`
+// in the requested format.
+fn write_image(sf: &SagasFile, indices: IndexImage, offset: (u32, u32), input: &Path, output: &Path,
+               index: Option<usize>, options: &Options) -> Result<Extracted, SagasError>
+{
+    let to_stdout = is_stdio(output);
+    let color_table = sf.get_color_table();
+    let indices = scale(indices, input, options);
+
+    if options.dry_run {
+        let (width, height) = indices.dimensions();
+        println!("{} -> {} ({}x{})", input.display(), output.display(), width, height);
+        return Ok(Extracted::Reported);
+    }
+
+    // -o is checked at startup, but a template can still give any extension.
+    let format = output_format(output, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // Everything is encoded in memory first, stdout can't seek and this way
+    // nothing is created when encoding fails.
+    let mut encoded = Vec::new();
+    if options.linear_exr {
+        #[cfg(feature = "exr")]
+        {
+            let mut rgba_image = color_table.render(&indices)?;
+            if let Some(radius) = options.edge_bleed {
+                edge_bleed(&mut rgba_image, radius);
+            }
+            write_linear_exr(&rgba_image, Cursor::new(&mut encoded))?;
+        }
+    } else if let Some(packing) = options.index_map {
+        // Straight from the indices, the palette isn't involved at all.
+        if options.premultiply || options.edge_bleed.is_some() || options.gamma != 1.0 {
+            warn!("{}: an index map has no colors, ignoring --premultiply, --edge-bleed and --gamma.", output.display());
+        }
+        index_map(&indices, packing).write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png)?;
+    } else if options.indexed || format == ImageFormat::Gif {
+        if options.premultiply || options.edge_bleed.is_some() || options.gamma != 1.0 || options.depth16 {
+            warn!("{}: indexed output keeps palette colors, ignoring --premultiply, --edge-bleed, --gamma and --depth16.",
+                  output.display());
+        }
+        if format == ImageFormat::Gif {
+            color_table.write_indexed_gif(&indices, &mut encoded)?;
+        } else {
+            color_table.write_indexed_png(&indices, &mut encoded)?;
+        }
+    } else {
+        let mut rgba_image = color_table.render(&indices)?;
+        if let Some(radius) = options.edge_bleed {
+            edge_bleed(&mut rgba_image, radius);
+        }
+        if options.premultiply {
+            for pixel in rgba_image.pixels_mut() {
+                *pixel = premultiplied(*pixel);
+            }
+        }
+        if options.depth16 && format != ImageFormat::Png {
+            warn!("{}: --depth16 only applies to PNG output, writing 8 bits per channel.", output.display());
+        }
+        if options.raw_planar {
+            apply_gamma(&mut rgba_image, options.gamma);
+            encoded = to_planar(&rgba_image, !options.strip_alpha);
+        } else if options.depth16 && format == ImageFormat::Png {
+            // Gamma is applied while widening so none of the precision is lost.
+            write_png16(&to_rgba16(&rgba_image, options.gamma), &mut encoded)?;
+        } else if matches!(format, ImageFormat::Bmp | ImageFormat::Jpeg) {
+            apply_gamma(&mut rgba_image, options.gamma);
+            // No (reliable) alpha in these, so flatten instead of dropping it.
+            flatten(&rgba_image, options.background.rgb())
+                .write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::from(format))?;
+        } else {
+            apply_gamma(&mut rgba_image, options.gamma);
+            rgba_image.write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::from(format))?;
+        }
+    }
+
+    if to_stdout {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&encoded)?;
+        stdout.flush()?;
+    } else if !write_output(output, &encoded, options)? {
+        return Ok(Extracted::Skipped(output.to_path_buf()));
+    }
+    let mut bytes_written = encoded.len() as u64;
+
+    // A RAW file has no header, so the layout goes next to it.
+    if options.raw_planar && !to_stdout {
+        let (width, height) = indices.dimensions();
+        let channels = if options.strip_alpha { "RGB" } else { "RGBA" };
+        let info = serde_json::to_string_pretty(&json!({
+            "width": width,
+            "height": height,
+            "channels": channels,
+            "bits_per_channel": 8,
+            "layout": "planar",
+        })).unwrap() + "\n";
+        if write_output(&output.with_extension("json"), info.as_bytes(), options)? {
+            bytes_written += info.len() as u64;
+        }
+    }
+
+    // What was written, after cropping and scaling.
+    let thumbnail = match options.contact_sheet {
+        Some(_) => Some(fit_nearest(&color_table.render(&indices)?, options.cell_size)),
+        None => None,
+    };
+
+    if let Some(path) = &options.dump_indices {
+        // Numbered like the images when an archive has several of them.
+        let path = index.map_or_else(|| path.clone(), |i| numbered_path(path, i));
+        let header = sf.get_header();
+        if write_output(&path, sf.get_image(), options)? {
+            bytes_written += sf.get_image().len() as u64;
+        }
+        let mut info = path.clone().into_os_string();
+        info.push(".txt");
+        let text = format!("# one palette index per byte, row major\nwidth={}\nheight={}\n", header.width, header.height);
+        if write_output(Path::new(&info), text.as_bytes(), options)? {
+            bytes_written += text.len() as u64;
+        }
+    }
+
+    let header = sf.get_header();
+    Ok(Extracted::Written(Output {
+        input: input.to_path_buf(),
+        path: output.to_path_buf(),
+        width: header.width,
+        height: header.height,
+        palette_size: color_table.colors.len(),
+        source_path: header.source_path().into_owned(),
+        index_hash: sf.index_hash(),
+        offset,
+        bytes_written,
+        thumbnail,
+    }))
 }
 
-impl<R> FromReader<R> for SagasColor
-    where R : BufRead + Seek
-{
-    fn from_reader(rd: &mut R) -> Result<Self> {
-        let (r,g, b, a) = (
-            rd.read_u8()?,
-            rd.read_u8()?,
-            rd.read_u8()?,
-            rd.read_u8()?,
-        );
-
-        let a = if a != 0 {
-            (((a as u16) << 1) - 1) as u8
+// Writes --palette-image and --export-palette next to `output`, returning the
+// number of bytes written.
+fn write_palette_outputs(color_table: &SagasColorLUT, output: &Path, options: &Options) -> Result<u64, SagasError> {
+    let mut bytes_written = 0;
+    if options.palette_image {
+        let mut name = output.file_stem().unwrap_or_default().to_os_string();
+        name.push("_palette.png");
+        // Sorting only changes the swatches, never the extracted image.
+        let swatches = if options.sort_palette {
+            color_table.sorted_by_luminance().swatch_image(16)
         } else {
-            a
+            color_table.swatch_image(16)
         };
+        let mut encoded = Vec::new();
+        swatches.write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png)?;
+        if write_output(&output.with_file_name(name), &encoded, options)? {
+            bytes_written += encoded.len() as u64;
+        }
+    }
 
-        Ok(SagasColor {
-            r,
-            g,
-            b,
-            a
-        })
+    if let Some(format) = options.export_palette {
+        let name = output.file_stem().unwrap_or_default().to_string_lossy();
+        let mut encoded = Vec::new();
+        color_table.export(format, &name, &mut encoded)?;
+        if write_output(&output.with_extension(format.extension()), &encoded, options)? {
+            bytes_written += encoded.len() as u64;
+        }
     }
+    Ok(bytes_written)
 }
 
-impl<R> FromReader<R> for SagasColorLUT
+// The fast path behind --palette-only and --list, checking the signature
+// first like archives do.
+fn read_header_and_palette<R>(r: &mut R, options: &Options) -> Result<SagasParts, SagasError>
     where R : BufRead + Seek
 {
-    fn from_reader(r: &mut R) -> Result<Self> {
-        let num_colors = 256; // Always 256 colors?
-        let mut colors = Vec::with_capacity(num_colors);
-        (0..num_colors).for_each(|_| colors.push(SagasColor::from_reader(r).unwrap()));
-
-        // Swizzle table.
-        for i in (0..(num_colors)).step_by(32) {
-            for (from, to) in (8..16).zip((16..24)) {
-                colors.swap(i + from, i + to);
-            }
-        }
-
-        Ok(SagasColorLUT {
-            colors,
-        })
+    if !SagasFile::probe(r) {
+        return Err(SagasError::BadSignature);
     }
+    SagasFile::read_header_and_palette_with(r, &options.decode)
 }
 
-impl<R> FromReader<R> for CString
+// --palette-only: reads just the header and color table of the first image
+// and writes the palette outputs, without ever loading the image itself.
+fn extract_palette<R>(r: &mut R, input: &Path, output: &Path, options: &Options) -> Result<Vec<Extracted>, SagasError>
     where R : BufRead + Seek
 {
-    fn from_reader(r: &mut R) -> Result<Self> {
-        let mut buffer = Vec::new();
-        r.read_until(0, &mut buffer)?;
-        buffer.pop();
-        Ok(unsafe { CString::from_vec_unchecked(buffer) })
+    let color_table = read_header_and_palette(r, options)?.lut;
+    if options.dry_run {
+        println!("{} -> palette of {} ({} colors)", input.display(), output.display(), color_table.colors.len());
+        return Ok(vec![Extracted::Reported]);
     }
+    let output = claim_output(output, input, options);
+    write_palette_outputs(&color_table, &output, options)?;
+    info!("Extracted the palette of {}", input.display());
+    Ok(vec![Extracted::Reported])
 }
 
-impl<R> FromReader<R> for SagasHeader
-    where R : BufRead + Seek
+// Converts an image into a Sagas file, copying the header from `template`.
+fn encode(input: &Path, output: &Path, template: Option<&Path>) -> Result<(), SagasError> {
+    let template = match template {
+        Some(path) => Some(parse_file(path)?),
+        None => None,
+    };
+
+    let image = if is_stdio(input) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        image::load_from_memory(&data)?
+    } else {
+        image::open(input)?
+    };
+    let sf = encode_rgba(&image.to_rgba8(), template.as_ref())?;
+
+    // Written to memory first since the writer needs to seek.
+    let mut data = Cursor::new(Vec::new());
+    sf.to_writer(&mut data)?;
+
+    if is_stdio(output) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data.get_ref())?;
+        stdout.flush()?;
+    } else {
+        create_parent_dir(output)?;
+        fs::write(output, data.get_ref())?;
+    }
+    Ok(())
+}
+
+// Extracts every file in `dir` into `out_dir`, descending into subdirectories
+// and mirroring their layout when `options.recursive` is set.
+// Files are extracted on a pool of `options.jobs` threads (0 picks one per CPU).
+fn extract_dir(dir: &Path, out_dir: &Path, options: &Options) {
+    let inputs = input_files(dir, options);
+    extract_all(dir, &inputs, out_dir, options, |input, output| extract(input, output, options));
+}
+
+type ZipInput = ZipArchive<BufReader<File>>;
+
+// Extracts every Sagas file in the zip `archive` as if it were a directory,
+// keeping the entries' own subdirectories under `out_dir`. Entries are only
+// decompressed when a worker gets to them, one at a time.
+fn extract_zip(archive: &Path, out_dir: &Path, options: &Options) {
+    let (zip, entries) = zip_entries(archive, options).unwrap_or_else(|e| {
+        error!("Could not read {}: {}", archive.display(), e);
+        process::exit(1);
+    });
+    let zip = Mutex::new(zip);
+    let inputs: Vec<_> = entries.keys().cloned().collect();
+    extract_all(archive, &inputs, out_dir, options, |input, output| {
+        let data = read_zip_entry(&zip, entries[input])?;
+        extract_from(&mut Cursor::new(data), input, output, options)
+    });
+}
+
+// The opened zip `archive` and the indices of its files that start with the
+// Sagas signature and pass --select and --exclude, keyed by `archive` joined
+// with their name so they're reported like the files of a directory.
+// Everything else the archive holds (readmes, other assets) is left out.
+// Unlike a directory, entries in subdirectories are always included.
+fn zip_entries(archive: &Path, options: &Options)
+    -> Result<(ZipInput, BTreeMap<PathBuf, usize>), SagasError>
 {
-    fn from_reader(r: &mut R) -> Result<Self> {
-        let unk0 = r.read_u64::<L>()?;
-        let unk1 = r.read_u32::<L>()?;
-        let unk2 = r.read_u32::<L>()?;
-        let unk3 = r.read_u32::<L>()?;
-        let unk4 = r.read_u32::<L>()?;
-        let string0 = CString::from_reader(r)?;
-
-        let unk5 = r.read_u32::<L>()?;
-        let unk6 = r.read_u32::<L>()?;
-        let unk7 = r.read_u32::<L>()?;
-        let image_offset = r.read_u32::<L>()?;
-
-        let width = r.read_u16::<L>()?;
-        let height = r.read_u16::<L>()?;
-
-        let unk9 = r.read_u32::<L>()?;
-        let unk10 = r.read_u32::<L>()?;
-        let color_table_offset = r.read_u32::<L>()?; // offset to beginning of color table
-
-        let unk12 = r.read_u16::<L>()?;
-        let unk13 = r.read_u16::<L>()?;
-        let unk14 = r.read_u32::<L>()?;
-        let string1 = CString::from_reader(r)?;
-
-        Ok(SagasHeader {
-            unk0,
-            unk1,
-            unk2,
-            unk3,
-            unk4,
-            string0,
-            unk5,
-            unk6,
-            unk7,
-            image_offset,
-            width,
-            height,
-            unk9,
-            unk10,
-            color_table_offset,
-            unk12,
-            unk13,
-            unk14,
-            string1,
-        })
+    let mut zip = ZipArchive::new(BufReader::new(File::open(archive)?)).map_err(io::Error::from)?;
+    let mut entries = BTreeMap::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(io::Error::from)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let Some(name) = entry.enclosed_name() else {
+            warn!("{}: skipping {}, its name would escape the output directory.", archive.display(), entry.name());
+            continue;
+        };
+        if !is_selected(&name, options) {
+            continue;
+        }
+
+        // Enough for the signature, without decompressing the rest.
+        let mut start = Vec::new();
+        entry.by_ref().take(16).read_to_end(&mut start)?;
+        if !SagasFile::probe(&mut Cursor::new(start)) {
+            info!("{}: skipping {}, not a Sagas file.", archive.display(), name.display());
+            continue;
+        }
+        if entry.size() > MAX_ZIP_ENTRY_SIZE {
+            warn!("{}: skipping {}, it's larger than {} bytes.", archive.display(), name.display(), MAX_ZIP_ENTRY_SIZE);
+            continue;
+        }
+        entries.insert(archive.join(name), i);
     }
+    Ok((zip, entries))
 }
 
-impl<R> FromReader<R> for SagasFile
-    where R : BufRead + Seek
+// Decompresses entry `index` of `zip`. The size in the archive can't be
+// trusted, so reading stops past MAX_ZIP_ENTRY_SIZE either way.
+fn read_zip_entry(zip: &Mutex<ZipInput>, index: usize) -> Result<Vec<u8>, SagasError> {
+    let mut zip = zip.lock().unwrap_or_else(|e| e.into_inner());
+    let mut entry = zip.by_index(index).map_err(io::Error::from)?;
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.by_ref().take(MAX_ZIP_ENTRY_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_ZIP_ENTRY_SIZE {
+        return Err(io::Error::other(format!("entry is larger than {} bytes", MAX_ZIP_ENTRY_SIZE)).into());
+    }
+    Ok(data)
+}
+
+// Extracts `inputs`, found under `root`, into the same relative directories
+// under `out_dir` with `extract_one(input, output)`, then reports the run.
+fn extract_all<F>(root: &Path, inputs: &[PathBuf], out_dir: &Path, options: &Options, extract_one: F)
+    where F : Fn(&Path, &Path) -> Result<Vec<Extracted>, SagasError> + Sync
 {
-    fn from_reader(r: &mut R) -> Result<Self> {
-        let header = SagasHeader::from_reader(r)?;
+    let pool = match ThreadPoolBuilder::new().num_threads(options.jobs).build() {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("Could not start worker threads: {}", e);
+            process::exit(1);
+        },
+    };
+
+    // Per-file output would tear the bar, so it's only shown when there is none.
+    let progress = if options.quiet || options.verbose || options.dry_run || options.report_only() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(inputs.len() as u64)
+    };
+    progress.set_style(ProgressStyle::with_template("{pos}/{len} [{bar:40}] {per_sec} ETA {eta}")
+        .unwrap()
+        .progress_chars("=> "));
+
+    // With --limit, inputs are handed out in batches no larger than the
+    // number of successes still missing, so the limit is never overshot.
+    let limit = options.limit.unwrap_or(usize::MAX);
+    let mut results: Vec<(PathBuf, Result<Vec<Extracted>, SagasError>)> = Vec::new();
+    let mut remaining = inputs;
+    loop {
+        let done = results.iter().filter(|(_, result)| result.as_ref().is_ok_and(|images| !all_skipped(images))).count();
+        let wanted = limit.saturating_sub(done).min(remaining.len());
+        if wanted == 0 {
+            break;
+        }
+        let (batch, rest) = remaining.split_at(wanted);
+        remaining = rest;
+        results.extend(pool.install(|| {
+            batch.par_iter()
+                .map(|input| {
+                    let relative = input.strip_prefix(root).unwrap_or(input);
+                    let target_dir = match relative.parent() {
+                        Some(parent) => out_dir.join(parent),
+                        None => out_dir.to_path_buf(),
+                    };
+
+                    let result = extract_one(input, &output_path_in(&target_dir, input, options.extension()));
+                    progress.inc(1);
+                    (input.clone(), result)
+                })
+                .collect::<Vec<_>>()
+        }));
+    }
+    progress.finish_and_clear();
+    let not_processed = remaining.len();
+
+    // Report in input order once everything is done, so the output is stable.
+    // An input only counts as skipped if none of its images were written.
+    let (mut succeeded, mut skipped, mut failed) = (0, 0, 0);
+    for (input, result) in &results {
+        if let Ok(images) = result {
+            report(input, images);
+        }
+        match result {
+            Ok(images) if all_skipped(images) => skipped += 1,
+            Ok(_) => succeeded += 1,
+            Err(e) => {
+                error!("Could not extract {}: {}", input.display(), e);
+                failed += 1;
+            },
+        }
+    }
+
+    if let Some(manifest) = &options.manifest {
+        let outputs = results.iter()
+            .filter_map(|(_, result)| result.as_ref().ok())
+            .flatten()
+            .filter_map(Extracted::written);
+        if let Err(e) = append_manifest(manifest, outputs) {
+            error!("Could not write manifest {}: {}", manifest.display(), e);
+            failed += 1;
+        }
+    }
+    if let Some(summary) = &options.json_summary {
+        if let Err(e) = write_json_summary(summary, &results, not_processed) {
+            error!("Could not write summary {}: {}", summary.display(), e);
+            failed += 1;
+        }
+    }
 
-        // Start reading the color table.
-        r.seek(SeekFrom::Start((header.color_table_offset) as _));
-        let lut = SagasColorLUT::from_reader(r)?;
+    if let Some(zip) = &options.zip {
+        if let Err(e) = zip.finish() {
+            error!("Could not finish the zip archive: {}", e);
+            failed += 1;
+        }
+    }
+
+    if let Some(path) = &options.contact_sheet {
+        if let Err(e) = write_contact_sheet(path, &results, options) {
+            error!("Could not write contact sheet {}: {}", path.display(), e);
+            failed += 1;
+        }
+    }
 
-        // Start reading the image.
-        r.seek(SeekFrom::Start(header.image_offset as _));
-        let mut image = Vec::with_capacity(header.width as usize * header.height as usize);
-        for _ in 0..header.width * header.height {
-            image.push(r.read_u8().unwrap());
+    print_timings(options);
+    if !options.quiet {
+        println!("{} succeeded, {} skipped, {} failed.", succeeded, skipped, failed);
+        if not_processed > 0 {
+            println!("Stopped at --limit {}, {} inputs were not processed.", limit, not_processed);
         }
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
 
-        Ok(SagasFile {
-            header,
-            lut,
-            image,
+// Globs given to --select or --exclude. A glob without a separator is
+// matched against the file name, one with a separator against the path
+// relative to the input directory.
+#[derive(Default)]
+struct Globs {
+    names: Option<GlobSet>,
+    paths: Option<GlobSet>,
+}
+
+impl Globs {
+    fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<Self, globset::Error> {
+        let (mut names, mut paths) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        let (mut any_names, mut any_paths) = (false, false);
+        for pattern in patterns {
+            if pattern.contains('/') {
+                paths.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+                any_paths = true;
+            } else {
+                names.add(Glob::new(pattern)?);
+                any_names = true;
+            }
+        }
+        Ok(Globs {
+            names: if any_names { Some(names.build()?) } else { None },
+            paths: if any_paths { Some(paths.build()?) } else { None },
         })
     }
+
+    fn is_empty(&self) -> bool {
+        self.names.is_none() && self.paths.is_none()
+    }
+
+    // `relative` is the path of a file relative to the input directory.
+    fn is_match(&self, relative: &Path) -> bool {
+        let name = relative.file_name().unwrap_or_default();
+        self.names.as_ref().is_some_and(|names| names.is_match(name))
+            || self.paths.as_ref().is_some_and(|paths| paths.is_match(relative))
+    }
+}
+
+// Whether the file at `relative` (to the input directory) passes --select
+// and --exclude.
+fn is_selected(relative: &Path, options: &Options) -> bool {
+    (options.select.is_empty() || options.select.is_match(relative)) && !options.exclude.is_match(relative)
+}
+
+// Files in `dir` sorted by name, including those in subdirectories when
+// `recursive` is set, and only those passing --select and --exclude.
+fn input_files(dir: &Path, options: &Options) -> Vec<PathBuf> {
+    let walker = WalkDir::new(dir)
+        .min_depth(1)
+        .max_depth(if options.recursive { usize::MAX } else { 1 })
+        .follow_links(false)
+        .sort_by_file_name();
+
+    let wanted = |path: &Path| is_selected(path.strip_prefix(dir).unwrap_or(path), options);
+
+    let mut inputs = Vec::new();
+    for entry in walker {
+        match entry {
+            // Symlinked files are fine, only symlinked directories aren't descended.
+            Ok(entry) if entry.path().is_file() => if wanted(entry.path()) {
+                inputs.push(entry.into_path());
+            },
+            Ok(_) => {},
+            Err(e) => error!("Could not read {}: {}", dir.display(), e),
+        }
+    }
+    inputs
 }
 
-impl SagasFile {
-    fn get_header(&self) -> &SagasHeader {
-        &self.header
+// Parses every image in `input` and checks it's internally consistent,
+// without writing anything.
+// Returns warnings for files that pass but look suspicious.
+fn verify(input: &Path, options: &Options) -> Result<Vec<String>, SagasError> {
+    let data = if is_stdio(input) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        data
+    } else {
+        fs::read(input)?
+    };
+
+    let mut warnings = Vec::new();
+    for sf in SagasArchive::from_reader_with(&mut Cursor::new(data), &options.decode)?.files {
+        let header = sf.get_header();
+        if header.width as usize * header.height as usize != sf.get_image().len() {
+            return Err(SagasError::BadDimensions);
+        }
+        sf.check_indices()?;
+        warnings.extend(header.dimension_warnings());
     }
+    Ok(warnings)
+}
 
-    fn get_color_table(&self) -> &SagasColorLUT {
-        &self.lut
+// Verifies each input, printing PASS or FAIL with the reason for every one
+// (plus a WARN line per warning), and exits with an error if any failed.
+fn verify_all(inputs: &[PathBuf], options: &Options) {
+    let mut failed = false;
+    for input in inputs {
+        match verify(input, options) {
+            Ok(warnings) => {
+                if !options.quiet {
+                    println!("PASS {}", input.display());
+                }
+                for warning in warnings {
+                    println!("WARN {}: {}", input.display(), warning);
+                }
+            },
+            Err(e) => {
+                println!("FAIL {}: {}", input.display(), e);
+                failed = true;
+            },
+        }
+    }
+    if failed {
+        process::exit(1);
     }
+}
+
+// Content hashes (as printed by --hash) of every image of `input`, named by
+// the input's path below `root` with `/` separators, so manifests compare
+// across platforms. Images of an archive get `#<index>` appended.
+fn checksums(input: &Path, root: &Path, options: &Options) -> Result<Vec<(String, u64)>, SagasError> {
+    let data = if is_stdio(input) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        data
+    } else {
+        fs::read(input)?
+    };
+    let relative = input.strip_prefix(root).unwrap_or(input);
+    let name = relative.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>().join("/");
+
+    let files = SagasArchive::from_reader_with(&mut Cursor::new(data), &options.decode)?.files;
+    let numbered = files.len() > 1;
+    Ok(files.iter()
+        .enumerate()
+        .map(|(i, sf)| (if numbered { format!("{}#{}", name, i) } else { name.clone() }, sf.content_hash()))
+        .collect())
+}
 
-    fn get_image(&self) -> &[u8] {
-        self.image.as_slice()
+// --write-checksums: writes a `<hash>  <name>` line per image of `inputs`
+// to `path`. Inputs that can't be decoded are reported and left out, and
+// make the run fail.
+fn write_checksums(path: &Path, inputs: &[PathBuf], root: &Path, options: &Options) {
+    let mut manifest = String::new();
+    let mut failed = false;
+    for input in inputs {
+        match checksums(input, root, options) {
+            Ok(hashes) => {
+                for (name, hash) in hashes {
+                    manifest += &format!("{:016x}  {}\n", hash, name);
+                }
+            },
+            Err(e) => {
+                error!("Could not read {}: {}", input.display(), e);
+                failed = true;
+            },
+        }
+    }
+    if let Err(e) = create_parent_dir(path).and_then(|_| fs::write(path, manifest)) {
+        error!("Could not write checksums {}: {}", path.display(), e);
+        failed = true;
+    }
+    if failed {
+        process::exit(1);
     }
 }
 
-fn main() {
-    let matches = App::new("dbz-sagas-extractor")
+// --verify-checksums: decodes `inputs` again and checks every image against
+// the manifest at `path`, printing PASS, MISMATCH or FAIL per image, then
+// MISSING for manifest entries no input produced. Images the manifest
+// doesn't know are EXTRA. Exits with an error unless everything passed.
+fn verify_checksums(path: &Path, inputs: &[PathBuf], root: &Path, options: &Options) {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| {
+        error!("Could not read checksums {}: {}", path.display(), e);
+        process::exit(1);
+    });
+    let mut expected = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match line.split_once("  ").map(|(hash, name)| (u64::from_str_radix(hash, 16), name)) {
+            Some((Ok(hash), name)) => expected.push((name.to_string(), hash, false)),
+            _ => {
+                error!("{}:{}: expected '<hash>  <name>'", path.display(), i + 1);
+                process::exit(1);
+            },
+        }
+    }
+
+    let mut failed = false;
+    for input in inputs {
+        let hashes = match checksums(input, root, options) {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                println!("FAIL {}: {}", input.display(), e);
+                failed = true;
+                continue;
+            },
+        };
+        for (name, hash) in hashes {
+            match expected.iter_mut().find(|(expected_name, _, _)| *expected_name == name) {
+                Some((_, expected_hash, seen)) => {
+                    *seen = true;
+                    if *expected_hash == hash {
+                        if !options.quiet {
+                            println!("PASS {}", name);
+                        }
+                    } else {
+                        println!("MISMATCH {}: expected {:016x}, got {:016x}", name, expected_hash, hash);
+                        failed = true;
+                    }
+                },
+                None => {
+                    println!("EXTRA {}", name);
+                    failed = true;
+                },
+            }
+        }
+    }
+    for (name, _, _) in expected.iter().filter(|(_, _, seen)| !seen) {
+        println!("MISSING {}", name);
+        failed = true;
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+// --list: one line per input with its dimensions, palette size and source
+// path, in aligned columns. Only headers and palettes are read.
+fn list(inputs: &[PathBuf], options: &Options) {
+    let mut rows = Vec::new();
+    let mut failed = false;
+    for input in inputs {
+        if rows.len() >= options.limit.unwrap_or(usize::MAX) {
+            break;
+        }
+        let parsed = if is_stdio(input) {
+            let mut data = Vec::new();
+            io::stdin().lock().read_to_end(&mut data)
+                .map_err(SagasError::from)
+                .and_then(|_| read_header_and_palette(&mut Cursor::new(data), options))
+        } else {
+            File::open(input)
+                .map_err(SagasError::from)
+                .and_then(|file| read_header_and_palette(&mut BufReader::new(file), options))
+        };
+        match parsed {
+            Ok(SagasParts { header, lut, .. }) => rows.push([
+                input.display().to_string(),
+                format!("{}x{}", header.width, header.height),
+                lut.colors.len().to_string(),
+                header.source_path().into_owned(),
+            ]),
+            Err(e) => {
+                error!("Could not read {}: {}", input.display(), e);
+                failed = true;
+            },
+        }
+    }
+
+    let path_width = rows.iter().map(|row| row[0].len()).max().unwrap_or(0);
+    let size_width = rows.iter().map(|row| row[1].len()).max().unwrap_or(0);
+    for [path, size, palette_size, source] in &rows {
+        println!("{:<path_width$}  {:>size_width$}  {:>3}  {}", path, size, palette_size, source);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+// --compare: prints whether `a` and `b` render identically, and where they
+// differ if not. Exits with an error when they differ, like `cmp`.
+fn compare(a: &Path, b: &Path, options: &Options) -> Result<bool, SagasError> {
+    let read = |path: &Path| SagasFile::from_reader_with(&mut BufReader::new(File::open(path)?), &options.decode);
+    let (first, second) = (read(a)?, read(b)?);
+    let (ha, hb) = (first.get_header(), second.get_header());
+    let Some(mut pixels) = first.differing_pixels(&second) else {
+        println!("{} and {} differ in size: {}x{} vs {}x{}", a.display(), b.display(), ha.width, ha.height, hb.width, hb.height);
+        return Ok(false);
+    };
+    match pixels.next() {
+        None => {
+            println!("{} and {} render identically", a.display(), b.display());
+            Ok(true)
+        },
+        Some((x, y)) => {
+            let count = 1 + pixels.count();
+            println!("{} and {} differ in {} pixel{}, the first at ({}, {})",
+                     a.display(), b.display(), count, if count == 1 { "" } else { "s" }, x, y);
+            Ok(false)
+        },
+    }
+}
+
+// Loads an external palette, picking the format from the file extension.
+fn load_palette(path: &Path) -> Result<SagasColorLUT, SagasError> {
+    let format: PaletteFormat = path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .parse()
+        .map_err(SagasError::BadPalette)?;
+    let lut = SagasColorLUT::import(format, &mut BufReader::new(File::open(path)?))?;
+    if !(1..=256).contains(&lut.colors.len()) {
+        return Err(SagasError::BadPalette(format!("expected 1 to 256 colors, got {}", lut.colors.len())));
+    }
+    Ok(lut)
+}
+
+// Parses an optional argument value, exiting with a message if it's invalid.
+fn parse_arg<T>(matches: &ArgMatches, name: &str) -> Option<T>
+    where T : FromStr, T::Err : fmt::Display
+{
+    match matches.value_of(name).map(str::parse) {
+        None => None,
+        Some(Ok(value)) => Some(value),
+        Some(Err(e)) => {
+            eprintln!("Invalid --{}: {}", name, e);
+            process::exit(1);
+        },
+    }
+}
+
+fn parse_globs(matches: &ArgMatches, name: &str) -> Globs {
+    match matches.values_of(name).map(Globs::new) {
+        None => Globs::default(),
+        Some(Ok(globs)) => globs,
+        Some(Err(e)) => {
+            eprintln!("Invalid --{}: {}", name, e);
+            process::exit(1);
+        },
+    }
+}
+
+// Flags every subcommand takes, also accepted before the subcommand.
+const SHARED_ARGS: &str = "
+    -v, --verbose 'Also dump the parsed structures of every file'
+    -q, --quiet 'Only print errors, not the line per file or the summary'
+    -i, --input=[RAW] 'Path to binary data, a directory of files, a .zip of them, or - for stdin (default)'
+    -o, --output=[PATH] 'Path to write the image to, - for stdout, or the output directory for directory or zip input (defaults to out/<input stem>.png, or stdout for stdin input)'
+    -r, --recursive 'Descend into subdirectories of a directory input'
+    -j, --jobs=[N] 'Number of files to extract in parallel for directory input (defaults to one per CPU)'
+    --limit=[N] 'With a directory input, stop after N inputs were extracted (or listed) successfully'
+    --alpha-mode=[MODE] 'How palette alpha is decoded: double (default), raw or opaque'
+    --no-swizzle 'Keep the palette in stored order instead of undoing the PS2 swizzle'
+    --palette-size=[N] 'Number of palette entries, overriding the header'
+    --bpp=[BITS] 'Bits per pixel of the image data, 4 or 8, overriding the header'
+    --endian=[ORDER] 'Byte order of the header: le (default, PS2) or be'
+    --force-dimensions=[WxH] 'Ignore the header width and height and use these instead'
+    --color-offset=[N] 'Read the palette at this offset (decimal or 0x hex) instead of the header's'
+    --image-offset=[N] 'Read the image at this offset (decimal or 0x hex) instead of the header's'
+    --palette-after-image 'Read the palette from right before the image data, whatever the header says (done anyway when its palette offset is unset)'
+    --stride=[N] 'Bytes per stored image row, for dumps whose rows are padded past the width'
+";
+
+// `extract`: decoding, render and output flags.
+const EXTRACT_ARGS: &str = "
+    --name-from-header 'Name output files after the source path embedded in the header'
+    --output-template=[TEMPLATE] 'Name outputs like {stem}_{width}x{height}.png, also {name} (from the header) and {index}'
+    --route-by-header=[STRING] 'Write outputs into a subdirectory from a header string: string0 (the source path's directory, e.g. sprites) or string1 (the texture name's prefix, e.g. Bit08)'
+    --manifest=[CSV] 'Append a row describing every written image to this CSV file'
+    --json-summary=[PATH] 'Write a JSON report of the run (counts, errors, bytes written) to this file'
+    --zip=[PATH] 'With a directory or zip input, write every output into this zip archive instead of loose files'
+    --contact-sheet=[PATH] 'With a directory or zip input, also tile every extracted image into this PNG'
+    --sheet-columns=[N] 'Number of columns of the --contact-sheet (default 8)'
+    --cell-size=[PX] 'Size of the square --contact-sheet cells in pixels (default 128)'
+    --sheet-labels 'Write the file name under every --contact-sheet cell'
+    --timings 'Print how long every input took to decode and to save to stderr, and the spread over the run (also with -q)'
+    --dry-run 'Parse the input and print where each image would be written, without writing anything'
+    --assume-alpha-max=[N] 'Stored alpha that --alpha-mode double decodes to 255 (defaults to 128), for variants that cap it elsewhere'
+    --alpha-scale=[F] 'Multiply the decoded alpha by F, clamped to 0-255 (after --alpha-mode)'
+    --alpha-offset=[N] 'Add N (may be negative) to the decoded alpha, after --alpha-scale'
+    --palette-from=[FILE] 'Recolor with the palette in an .act or .pal file, keeping the original alpha'
+    --oob-color=[COLOR] 'Color (#RRGGBB or #RRGGBBAA) for pixels whose index is past the end of the palette (defaults to #ff00ffff)'
+    --strict 'Fail on pixels whose index is past the end of the palette instead of using --oob-color'
+    --transparent-index=[N] 'Make palette entry N fully transparent'
+    --transparent-color=[COLOR] 'Make every palette entry with this color (#RRGGBB, alpha is ignored) fully transparent'
+    --crop=[X,Y,W,H] 'Only extract the given rectangle of the image'
+    --split-atlas=[COLS,ROWS] 'Write every cell of a COLS x ROWS grid of sprites as its own image, named <name>_r<row>_c<column>'
+    --autocrop 'Trim fully transparent borders, after --crop or of every --split-atlas cell (the offset is recorded in --manifest)'
+    --autocrop-padding=[N] 'Keep N transparent pixels around the trimmed image (implies --autocrop)'
+    --flip-vertical 'Flip the image upside down (combine with --flip-horizontal to rotate 180 degrees)'
+    --flip-horizontal 'Mirror the image left to right'
+    --scale=[N] 'Enlarge the image N times with nearest neighbour filtering, after cropping and flipping'
+    --no-clobber 'Skip inputs whose output file already exists'
+    --overwrite 'Replace existing output files (the default)'
+    --animate 'Write the images of a multi-image file as one animated GIF instead of numbered files'
+    --gamma=[F] 'Raise the RGB channels to this power, alpha is kept (defaults to 1, unchanged; 2.2 linearizes)'
+    --linear-exr 'Write linear light OpenEXR files instead (needs the exr feature)'
+    --raw-planar 'Write a planar RAW (every R, then G, B and A byte) and its layout as <name>.json (A is left out with --strip-alpha)'
+    --index-map 'Write a PNG of palette indices instead of colors, packed as --index-map-packing says, for shader palette lookups'
+    --index-map-packing=[PACKING] 'red (default): R is the index, G and B are 0; gray: R, G and B are the index. Alpha is 255 either way'
+    --depth16 'Write a 16 bits per channel PNG, after --gamma (not with --indexed or GIF output)'
+    --fps=[N] 'Frames per second for --animate (defaults to 10)'
+    --format=[FORMAT] 'Output image format: png, bmp, tga, gif or jpg (defaults to the output extension, then png)'
+    --background=[COLOR] 'Color (#RRGGBB) to composite transparent pixels over for BMP and JPEG output (defaults to #ff00ff)'
+    --edge-bleed=[N] 'Fill the color of transparent pixels up to N pixels from visible ones, to avoid halos when filtering'
+    --strip-alpha 'Make every pixel fully opaque, keeping the stored colors (--background then has no effect)'
+    --premultiply 'Multiply colors by their alpha in RGBA output (not possible with --indexed or GIF output)'
+    --indexed 'Write an 8-bit palette PNG instead of expanding to RGBA'
+    --export-palette=[FORMAT] 'Also write the palette next to the PNG (act, pal or gpl)'
+    --dump-indices=[PATH] 'Also write the raw palette indices (one byte per pixel) to PATH, and the dimensions to PATH.txt'
+    --palette-image 'Also write a <name>_palette.png swatch grid of the palette'
+    --sort-palette 'Order the --palette-image swatches by luminance, then alpha'
+";
+
+// `info`: print about the inputs instead of extracting.
+const INFO_ARGS: &str = "
+    --verify 'Check that the input parses and all pixels are in the palette, without writing anything'
+    --write-checksums=[PATH] 'Write the --hash of every input image to PATH, named by its path below the input directory'
+    --verify-checksums=[PATH] 'Decode the inputs again and check them against a --write-checksums file, without writing anything'
+    --list 'Print the size, palette size and source path of every input (the first image of an archive) without extracting'
+    --index-histogram 'Print how often each palette index is used as JSON and skip extraction'
+    --alpha-histogram 'Print how many pixels have a stored alpha of 0, 1-127, 128, 129-254 and 255 as JSON, with the --alpha-mode that suggests'
+    --hash 'Print a hash of the visible content (palette and pixels) and skip extraction'
+    --strings 'Print the output path and both embedded strings, tab separated, and skip extraction'
+    --dump-header 'Print the header as JSON and skip extraction'
+    --annotate 'Print a hexdump of the header with every field labeled and skip extraction'
+";
+
+// `palette`: the outputs of --palette-only.
+const PALETTE_ARGS: &str = "
+    --export-palette=[FORMAT] 'Write the palette as an act, pal or gpl file'
+    --palette-image 'Write a <name>_palette.png swatch grid of the palette'
+    --sort-palette 'Order the --palette-image swatches by luminance, then alpha'
+    --dry-run 'Print where the palette would be written, without writing anything'
+";
+
+// `encode`, which also takes -i and -o.
+const ENCODE_ARGS: &str = "
+    --template=[FILE] 'Sagas file to copy the header from when encoding'
+";
+
+// Only taken without a subcommand, where they pick the mode.
+const LEGACY_ARGS: &str = "
+    --encode 'Convert an image (-i) into a Sagas file (-o, defaults to out/<input stem>.dbl)'
+    --template=[FILE] 'Sagas file to copy the header from when encoding'
+    --palette-only 'Only read the palette (of the first image) and write --palette-image/--export-palette'
+";
+
+// The arguments of `args_from_usage` style lines.
+fn usage_args(usage: &'static str) -> Vec<Arg<'static, 'static>> {
+    usage.lines().map(str::trim).filter(|line| !line.is_empty()).map(Arg::from_usage).collect()
+}
+
+// One value per occurrence, so a glob is never mistaken for the subcommand.
+fn glob_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("select")
+            .long("select")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1)
+            .help("With a directory or zip input, only read files matching GLOB (the file name, or the path below the directory or inside the archive if GLOB contains a /)"),
+        Arg::with_name("exclude")
+            .long("exclude")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1)
+            .help("With a directory or zip input, skip files matching GLOB (matched like --select)"),
+    ]
+}
+
+fn compare_arg() -> Arg<'static, 'static> {
+    Arg::with_name("compare")
+        .long("compare")
+        .value_names(&["A", "B"])
+        .number_of_values(2)
+        .help("Check whether two files render to the same pixels, ignoring how they're stored")
+}
+
+// Without a subcommand every flag is still taken, the way it was before
+// there were subcommands. Those are hidden from --help.
+fn app() -> App<'static, 'static> {
+    let shared: Vec<_> = usage_args(SHARED_ARGS).into_iter().chain(glob_args()).map(|arg| arg.global(true)).collect();
+    let legacy: Vec<_> = [EXTRACT_ARGS, INFO_ARGS, LEGACY_ARGS].into_iter()
+        .flat_map(usage_args)
+        .chain([compare_arg()])
+        .map(|arg| arg.hidden(true))
+        .collect();
+
+    App::new("dbz-sagas-extractor")
         .author("Ricky van den Waardenburg")
         .about("Extracts bitmaps from DBZ Saga indexed binary graphics format.")
-        .args_from_usage(
-            "-i, --input=[RAW] 'Path to binary data'
-            ")
-        .get_matches();
+        .after_help("Running without a subcommand (taking the flags of all of them) is deprecated and stops working in the next release.")
+        .args(&shared)
+        .args(&legacy)
+        .subcommand(SubCommand::with_name("extract")
+            .about("Write every input image as a PNG, or another format")
+            .args(&usage_args(EXTRACT_ARGS)))
+        .subcommand(SubCommand::with_name("info")
+            .about("Print about the inputs without extracting them (like --list if no flag is given)")
+            .args(&usage_args(INFO_ARGS))
+            .arg(compare_arg()))
+        .subcommand(SubCommand::with_name("palette")
+            .about("Only read the palette (of the first image) and write it out")
+            .args(&usage_args(PALETTE_ARGS)))
+        .subcommand(SubCommand::with_name("encode")
+            .about("Convert an image (-i) into a Sagas file (-o, defaults to out/<input stem>.dbl)")
+            .args(&usage_args(ENCODE_ARGS)))
+}
 
-    // Read binary file.
-    let path = match matches.value_of("input") {
-        None => {
-            println!("Missing binary file path parameter (-i, --input).");
-            return;
+fn main() {
+    let app_matches = app().get_matches();
+    let (command, matches) = match app_matches.subcommand() {
+        (name, Some(matches)) => (Some(name), matches),
+        _ => (None, &app_matches),
+    };
+
+    // One line per extracted file by default, -q leaves only errors and -v
+    // adds the parsed structures.
+    let level = if matches.is_present("verbose") {
+        LevelFilter::Debug
+    } else if matches.is_present("quiet") {
+        LevelFilter::Error
+    } else {
+        LevelFilter::Info
+    };
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn)
+        .filter_module(module_path!(), level)
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level.as_str().to_ascii_lowercase(), record.args()),
+        })
+        .parse_default_env()
+        .init();
+
+    if command.is_none() {
+        warn!("running without a subcommand is deprecated, use extract, info, palette or encode (see --help).");
+    }
+
+    // Read binary file, or stdin when no input is given.
+    let path = Path::new(matches.value_of("input").unwrap_or("-"));
+
+    let decode = DecodeOptions {
+        // The histogram is about what's stored, before any alpha mode.
+        alpha_mode: if matches.is_present("alpha-histogram") {
+            AlphaMode::Raw
+        } else {
+            parse_arg(matches, "alpha-mode").unwrap_or_default()
         },
-        Some(path) => path,
+        alpha_max: parse_arg(matches, "assume-alpha-max").unwrap_or(DecodeOptions::default().alpha_max),
+        deswizzle: !matches.is_present("no-swizzle"),
+        palette_size: parse_arg(matches, "palette-size"),
+        bits_per_pixel: parse_arg(matches, "bpp"),
+        endian: parse_arg(matches, "endian").unwrap_or_default(),
+        stride: parse_arg(matches, "stride"),
+        dimensions: parse_arg::<Dimensions>(matches, "force-dimensions").map(|Dimensions(width, height)| (width, height)),
+        color_table_offset: parse_arg(matches, "color-offset").map(|OffsetArg(offset)| offset),
+        image_offset: parse_arg(matches, "image-offset").map(|OffsetArg(offset)| offset),
+        inline_palette: matches.is_present("palette-after-image"),
     };
 
-    // Read the Sagas header.
-    let bin = File::open(path);
-    if bin.is_err() {
-        println!("File not found.");
+    let mut options = Options {
+        format: parse_arg(matches, "format"),
+        verbose: matches.is_present("verbose"),
+        quiet: matches.is_present("quiet"),
+        recursive: matches.is_present("recursive"),
+        select: parse_globs(matches, "select"),
+        exclude: parse_globs(matches, "exclude"),
+        jobs: parse_arg(matches, "jobs").unwrap_or(0),
+        decode,
+        dump_header: matches.is_present("dump-header"),
+        annotate: matches.is_present("annotate"),
+        export_palette: parse_arg(matches, "export-palette"),
+        indexed: matches.is_present("indexed"),
+        palette_image: matches.is_present("palette-image"),
+        palette_only: command == Some("palette") || matches.is_present("palette-only"),
+        sort_palette: matches.is_present("sort-palette"),
+        transparent_index: parse_arg(matches, "transparent-index"),
+        transparent_color: parse_arg(matches, "transparent-color"),
+        crop: parse_arg(matches, "crop"),
+        split_atlas: parse_arg(matches, "split-atlas"),
+        flip_vertical: matches.is_present("flip-vertical"),
+        flip_horizontal: matches.is_present("flip-horizontal"),
+        scale: parse_arg(matches, "scale"),
+        premultiply: matches.is_present("premultiply"),
+        index_histogram: matches.is_present("index-histogram"),
+        alpha_histogram: matches.is_present("alpha-histogram"),
+        no_clobber: matches.is_present("no-clobber"),
+        background: parse_arg(matches, "background").unwrap_or(DEFAULT_BACKGROUND),
+        dry_run: matches.is_present("dry-run"),
+        strings: matches.is_present("strings"),
+        manifest: matches.value_of("manifest").map(PathBuf::from),
+        json_summary: matches.value_of("json-summary").map(PathBuf::from),
+        contact_sheet: matches.value_of("contact-sheet").map(PathBuf::from),
+        sheet_columns: parse_arg(matches, "sheet-columns").unwrap_or(8),
+        cell_size: parse_arg(matches, "cell-size").unwrap_or(128),
+        sheet_labels: matches.is_present("sheet-labels"),
+        gamma: parse_arg(matches, "gamma").unwrap_or(1.0),
+        alpha_scale: parse_arg(matches, "alpha-scale").unwrap_or(1.0),
+        alpha_offset: parse_arg(matches, "alpha-offset").unwrap_or(0),
+        depth16: matches.is_present("depth16"),
+        used_outputs: Mutex::new(HashSet::new()),
+        timings: matches.is_present("timings").then(|| Mutex::new(Vec::new())),
+        zip: None,
+        limit: parse_arg(matches, "limit"),
+        linear_exr: matches.is_present("linear-exr"),
+        raw_planar: matches.is_present("raw-planar"),
+        index_map: (matches.is_present("index-map") || matches.is_present("index-map-packing"))
+            .then(|| parse_arg(matches, "index-map-packing").unwrap_or_default()),
+        hash: matches.is_present("hash"),
+        dump_indices: matches.value_of("dump-indices").map(PathBuf::from),
+        edge_bleed: parse_arg(matches, "edge-bleed"),
+        strip_alpha: matches.is_present("strip-alpha"),
+        animate: matches.is_present("animate"),
+        fps: parse_arg(matches, "fps").unwrap_or(10.0),
+        strict: matches.is_present("strict"),
+        oob_color: parse_arg(matches, "oob-color").unwrap_or(DEFAULT_OOB_COLOR),
+        autocrop: matches.is_present("autocrop") || matches.is_present("autocrop-padding"),
+        autocrop_padding: parse_arg(matches, "autocrop-padding").unwrap_or(0),
+        palette_from: matches.value_of("palette-from").map(|path| {
+            load_palette(Path::new(path)).unwrap_or_else(|e| {
+                eprintln!("Invalid --palette-from: {}", e);
+                process::exit(1);
+            })
+        }),
+        // An explicit output file name wins over the header.
+        name_from_header: matches.is_present("name-from-header")
+            && (path.is_dir() || is_zip_input(path) || matches.value_of("output").is_none()),
+        output_template: parse_arg(matches, "output-template"),
+        route_by_header: parse_arg(matches, "route-by-header"),
+    };
+
+    if options.decode.bits_per_pixel.is_some_and(|bits| bits != 4 && bits != 8) {
+        eprintln!("--bpp must be 4 or 8");
+        process::exit(1);
+    }
+
+    if options.decode.palette_size.is_some_and(|size| !(1..=256).contains(&size)) {
+        eprintln!("Invalid --palette-size: must be between 1 and 256.");
+        process::exit(1);
+    }
+
+    if options.decode.alpha_max == 0 {
+        eprintln!("Invalid --assume-alpha-max: must be at least 1.");
+        process::exit(1);
+    }
+    if matches.is_present("assume-alpha-max") && (options.alpha_histogram || options.decode.alpha_mode != AlphaMode::Double) {
+        eprintln!("--assume-alpha-max only applies to --alpha-mode double.");
+        process::exit(1);
+    }
+
+    if options.alpha_histogram && matches.is_present("alpha-mode") {
+        eprintln!("--alpha-histogram counts the stored alpha, --alpha-mode doesn't apply.");
+        process::exit(1);
+    }
+
+    if options.indexed && options.format.is_some_and(|FormatArg(format)| format != ImageFormat::Png) {
+        eprintln!("--indexed only applies to PNG output.");
+        process::exit(1);
+    }
+
+    if options.decode.inline_palette && options.decode.color_table_offset.is_some() {
+        eprintln!("--palette-after-image and --color-offset both say where the palette is, give only one.");
+        process::exit(1);
+    }
+
+    if let Some(offset) = options.decode.color_table_offset {
+        warn!("--color-offset: ignoring the color table offset in every header, using {:#x}.", offset);
+    }
+    if let Some(offset) = options.decode.image_offset {
+        warn!("--image-offset: ignoring the image offset in every header, using {:#x}.", offset);
+    }
+    if let Some((width, height)) = options.decode.dimensions {
+        warn!("--force-dimensions: ignoring the width and height in every header, using {}x{}.", width, height);
+    }
+
+    if !(options.fps.is_finite() && options.fps > 0.0) {
+        eprintln!("Invalid --fps: must be a positive number.");
+        process::exit(1);
+    }
+
+    // Premultiplying zeroes the color of transparent pixels, undoing the bleed.
+    if options.premultiply && options.edge_bleed.is_some() {
+        eprintln!("--edge-bleed can't be combined with --premultiply.");
+        process::exit(1);
+    }
+
+    if options.name_from_header && options.output_template.is_some() {
+        eprintln!("--name-from-header and --output-template can't be combined, use {{name}} in the template.");
+        process::exit(1);
+    }
+
+    if options.no_clobber && matches.is_present("overwrite") {
+        eprintln!("--no-clobber and --overwrite can't be combined.");
+        process::exit(1);
+    }
+
+    if !options.alpha_scale.is_finite() || options.alpha_scale < 0.0 {
+        eprintln!("Invalid --alpha-scale: must be a number of at least 0.");
+        process::exit(1);
+    }
+
+    if options.linear_exr && !cfg!(feature = "exr") {
+        eprintln!("--linear-exr needs a build with the exr feature (cargo build --features exr).");
+        process::exit(1);
+    }
+
+    if options.linear_exr && (options.indexed || options.format.is_some() || options.depth16 || options.premultiply) {
+        eprintln!("--linear-exr can't be combined with --indexed, --format, --depth16 or --premultiply.");
+        process::exit(1);
+    }
+
+    if options.index_map.is_some() && (options.indexed || options.format.is_some() || options.depth16
+                                       || options.linear_exr || options.raw_planar) {
+        eprintln!("--index-map can't be combined with --indexed, --format, --depth16, --linear-exr or --raw-planar.");
+        process::exit(1);
+    }
+
+    if options.raw_planar && (options.indexed || options.format.is_some() || options.depth16 || options.linear_exr) {
+        eprintln!("--raw-planar can't be combined with --indexed, --format, --depth16 or --linear-exr.");
+        process::exit(1);
+    }
+
+    if !(options.gamma.is_finite() && options.gamma > 0.0) {
+        eprintln!("Invalid --gamma: must be a positive number.");
+        process::exit(1);
+    }
+
+    if options.depth16 && (options.indexed || options.format.is_some_and(|FormatArg(format)| format != ImageFormat::Png)) {
+        eprintln!("--depth16 only works with RGBA PNG output, not --indexed or other formats.");
+        process::exit(1);
+    }
+
+    // Palette entries are stored straight, so indexed output can't be premultiplied.
+    if options.premultiply && (options.indexed || options.format.is_some_and(|FormatArg(format)| format == ImageFormat::Gif)) {
+        eprintln!("--premultiply can't be combined with --indexed or GIF output.");
+        process::exit(1);
+    }
+
+    if options.sort_palette && !options.palette_image {
+        eprintln!("--sort-palette only applies to --palette-image.");
+        process::exit(1);
+    }
+
+    if options.palette_only && !(options.palette_image || options.export_palette.is_some()) {
+        eprintln!("--palette-only needs --palette-image or --export-palette to have something to write.");
+        process::exit(1);
+    }
+
+    if command == Some("encode") || matches.is_present("encode") {
+        let output = match matches.value_of("output") {
+            None if is_stdio(path) => PathBuf::from("-"),
+            None => output_path_in(Path::new("out"), path, "dbl"),
+            Some(output) => PathBuf::from(output),
+        };
+
+        if let Err(e) = encode(path, &output, matches.value_of("template").map(Path::new)) {
+            error!("Could not encode {}: {}", path.display(), e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(mut paths) = matches.values_of("compare") {
+        let (a, b) = (Path::new(paths.next().unwrap()), Path::new(paths.next().unwrap()));
+        match compare(a, b, &options) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                error!("Could not compare {} and {}: {}", a.display(), b.display(), e);
+                process::exit(2);
+            },
+        }
+    }
+
+    // `info` on its own lists the inputs.
+    let info_flags = ["verify", "write-checksums", "verify-checksums", "index-histogram", "alpha-histogram", "hash", "strings", "dump-header", "annotate", "compare"];
+    let listing = matches.is_present("list") || (command == Some("info") && !info_flags.iter().any(|&flag| matches.is_present(flag)));
+    let zip_input = is_zip_input(path);
+    if zip_input && (listing || ["verify", "write-checksums", "verify-checksums"].iter().any(|&flag| matches.is_present(flag))) {
+        eprintln!("--list, --verify and the checksum options take a file or directory, not a zip archive.");
+        process::exit(1);
+    }
+    if listing {
+        let inputs = if path.is_dir() { input_files(path, &options) } else { vec![path.to_path_buf()] };
+        list(&inputs, &options);
+        return;
+    }
+
+    if matches.is_present("write-checksums") && matches.is_present("verify-checksums") {
+        eprintln!("Give only one of --write-checksums and --verify-checksums.");
+        process::exit(1);
+    }
+
+    if let Some(manifest) = matches.value_of("write-checksums").or(matches.value_of("verify-checksums")) {
+        let (inputs, root) = if path.is_dir() {
+            (input_files(path, &options), path)
+        } else {
+            (vec![path.to_path_buf()], path.parent().unwrap_or(Path::new("")))
+        };
+        if matches.is_present("write-checksums") {
+            write_checksums(Path::new(manifest), &inputs, root, &options);
+        } else {
+            verify_checksums(Path::new(manifest), &inputs, root, &options);
+        }
+        return;
+    }
+
+    if matches.is_present("verify") {
+        let inputs = if path.is_dir() { input_files(path, &options) } else { vec![path.to_path_buf()] };
+        verify_all(&inputs, &options);
         return;
     }
 
-    let mut buf_reader = BufReader::new(bin.unwrap());
-    if let Ok(sf) = SagasFile::from_reader(&mut buf_reader) {
-        println!("{:#?}", sf);
+    if options.split_atlas.is_some() && (options.crop.is_some() || options.animate || options.dump_indices.is_some()) {
+        eprintln!("--split-atlas can't be combined with --crop, --animate or --dump-indices.");
+        process::exit(1);
+    }
+
+    let batch = path.is_dir() || zip_input;
+    if !batch && matches.is_present("zip") {
+        eprintln!("--zip takes a directory or zip input, not a single file.");
+        process::exit(1);
+    }
+
+    if !batch && options.contact_sheet.is_some() {
+        eprintln!("--contact-sheet takes a directory or zip input, not a single file.");
+        process::exit(1);
+    }
+
+    if options.cell_size == 0 {
+        eprintln!("Invalid --cell-size: must be at least 1");
+        process::exit(1);
+    }
 
-        let (header, image, color_table) = (sf.get_header(), sf.get_image(), sf.get_color_table());
-        let (width, height) = (header.width as usize, header.height as usize);
+    if batch && options.dump_indices.is_some() {
+        eprintln!("--dump-indices takes a single file input, not a directory or zip archive.");
+        process::exit(1);
+    }
 
-        let mut rgba_image: RgbaImage = RgbaImage::new(width as _, height as _);
-        for y in 0..height {
-            for x in 0..width {
-                let i = image[x + y * width] as usize;
-                let c : SagasColor = color_table.colors[i];
-                let (x, y) = (x as u32, y as u32);
-                rgba_image.put_pixel(x, y, Rgba([c.r, c.g, c.b, c.a]));
+    if batch {
+        let out_dir = Path::new(matches.value_of("output").unwrap_or("out"));
+        if let Some(zip) = matches.value_of("zip").map(Path::new) {
+            match ZipOutput::create(zip, out_dir) {
+                Ok(zip) => options.zip = Some(zip),
+                Err(e) => {
+                    error!("Could not create {}: {}", zip.display(), e);
+                    process::exit(1);
+                },
             }
         }
-        rgba_image.save(format!("out/test.png"));
+        if zip_input {
+            extract_zip(path, out_dir, &options);
+        } else {
+            extract_dir(path, out_dir, &options);
+        }
+        return;
+    }
+
+    let output = match matches.value_of("output") {
+        None if is_stdio(path) => PathBuf::from("-"),
+        None => output_path_in(Path::new("out"), path, options.extension()),
+        Some(output) => PathBuf::from(output),
+    };
+
+    if is_stdio(&output) && (options.export_palette.is_some() || options.palette_image || options.split_atlas.is_some()) {
+        eprintln!("--export-palette, --palette-image and --split-atlas need a file output, not stdout.");
+        process::exit(1);
+    }
+
+    // Animations are always GIFs, and the report and palette options don't write an image.
+    if !(options.animate || options.palette_only || options.report_only()) {
+        if let Err(e) = output_format(&output, &options) {
+            eprintln!("Invalid --output: {}.", e);
+            process::exit(1);
+        }
+    }
+
+    let results = [(path.to_path_buf(), extract(path, &output, &options))];
+    if let Some(summary) = &options.json_summary {
+        if let Err(e) = write_json_summary(summary, &results, 0) {
+            error!("Could not write summary {}: {}", summary.display(), e);
+            process::exit(1);
+        }
+    }
+    let extracted = match &results[0].1 {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            error!("Could not extract {}: {}", path.display(), e);
+            process::exit(1);
+        },
+    };
+    report(path, extracted);
+    print_timings(&options);
+
+    if let Some(manifest) = &options.manifest {
+        if let Err(e) = append_manifest(manifest, extracted.iter().filter_map(Extracted::written)) {
+            error!("Could not write manifest {}: {}", manifest.display(), e);
+            process::exit(1);
+        }
     }
-}
\ No newline at end of file
+}
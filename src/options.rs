@@ -0,0 +1,97 @@
+use std::str::FromStr;
+
+// Stored alpha that means fully opaque in PS2 style palettes.
+const DEFAULT_ALPHA_MAX: u8 = 128;
+
+// How the stored alpha byte is turned into an 8-bit alpha value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    #[default]
+    Double, // PS2 style 0-128 (or 0-`alpha_max`) range expanded to 0-255
+    Raw,    // byte is already full range
+    Opaque, // ignore the stored alpha entirely
+}
+
+impl AlphaMode {
+    pub fn apply(&self, a: u8) -> u8 {
+        self.apply_with_max(a, DEFAULT_ALPHA_MAX)
+    }
+
+    // Like `apply`, but `Double` takes `max` as the stored alpha of a fully
+    // opaque color. Values above it clamp to 255.
+    pub fn apply_with_max(&self, a: u8, max: u8) -> u8 {
+        match self {
+            AlphaMode::Double => (a as u32 * 255 / max.max(1) as u32).min(255) as u8,
+            AlphaMode::Raw => a,
+            AlphaMode::Opaque => 255,
+        }
+    }
+}
+
+impl FromStr for AlphaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "double" => Ok(AlphaMode::Double),
+            "raw" => Ok(AlphaMode::Raw),
+            "opaque" => Ok(AlphaMode::Opaque),
+            _ => Err(format!("unknown alpha mode '{}' (expected double, raw or opaque)", s)),
+        }
+    }
+}
+
+// Byte order of the integers in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little, // PS2
+    Big,
+}
+
+impl FromStr for Endian {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "le" | "little" => Ok(Endian::Little),
+            "be" | "big" => Ok(Endian::Big),
+            _ => Err(format!("unknown byte order '{}' (expected le or be)", s)),
+        }
+    }
+}
+
+// Knobs for decoding files that don't follow the common layout. The defaults
+// match what `FromReader::from_reader` does.
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    pub alpha_mode: AlphaMode,
+    pub alpha_max: u8, // stored alpha `AlphaMode::Double` maps to 255
+    pub deswizzle: bool, // undo the PS2 palette swizzle after reading the table
+    pub palette_size: Option<usize>, // number of palette entries, instead of the header's
+    pub bits_per_pixel: Option<u8>, // 4 or 8, instead of the header's
+    pub endian: Endian,
+    pub stride: Option<usize>, // bytes per stored image row, when rows are padded
+    pub dimensions: Option<(u16, u16)>, // width and height to use instead of the header's
+    pub color_table_offset: Option<u32>, // instead of the header's, still relative to the file start
+    pub image_offset: Option<u32>,       // same for the image data
+    pub inline_palette: bool, // the color table sits right before the image, whatever the header says
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            alpha_mode: AlphaMode::default(),
+            alpha_max: DEFAULT_ALPHA_MAX,
+            deswizzle: true,
+            palette_size: None,
+            bits_per_pixel: None,
+            endian: Endian::default(),
+            stride: None,
+            dimensions: None,
+            color_table_offset: None,
+            image_offset: None,
+            inline_palette: false,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use crate::{Result, SagasColor, SagasColorLUT, SagasError};
+
+// Palette file formats the color table can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    Act, // Adobe color table, 256 raw RGB triplets
+    Pal, // JASC-PAL text palette
+    Gpl, // GIMP palette
+}
+
+impl PaletteFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFormat::Act => "act",
+            PaletteFormat::Pal => "pal",
+            PaletteFormat::Gpl => "gpl",
+        }
+    }
+}
+
+impl FromStr for PaletteFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "act" => Ok(PaletteFormat::Act),
+            "pal" => Ok(PaletteFormat::Pal),
+            "gpl" => Ok(PaletteFormat::Gpl),
+            _ => Err(format!("unknown palette format '{}' (expected act, pal or gpl)", s)),
+        }
+    }
+}
+
+impl SagasColorLUT {
+    // Writes the table in the given format. `.act` and `.pal` have no alpha
+    // channel so it is dropped, `.gpl` keeps it in each entry's name column.
+    pub fn export<W: Write>(&self, format: PaletteFormat, name: &str, w: &mut W) -> Result<()> {
+        match format {
+            PaletteFormat::Act => {
+                // Always 768 bytes, unused entries are left black.
+                let mut table = [0u8; 768];
+                for (entry, c) in table.chunks_mut(3).zip(&self.colors) {
+                    entry.copy_from_slice(&[c.r, c.g, c.b]);
+                }
+                w.write_all(&table)?;
+            },
+            PaletteFormat::Pal => {
+                write!(w, "JASC-PAL\r\n0100\r\n{}\r\n", self.colors.len())?;
+                for c in &self.colors {
+                    write!(w, "{} {} {}\r\n", c.r, c.g, c.b)?;
+                }
+            },
+            PaletteFormat::Gpl => {
+                writeln!(w, "GIMP Palette")?;
+                writeln!(w, "Name: {}", name)?;
+                writeln!(w, "Columns: 16")?;
+                writeln!(w, "#")?;
+                for (i, c) in self.colors.iter().enumerate() {
+                    writeln!(w, "{:3} {:3} {:3}\tIndex {} (alpha {})", c.r, c.g, c.b, i, c.a)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Reads a table written by `export` (or any other tool) in `.act` or
+    // `.pal` format. Neither has per-entry alpha so every entry is opaque,
+    // except the transparent index of an `.act` that has one.
+    pub fn import<R: Read>(format: PaletteFormat, r: &mut R) -> Result<Self> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        let opaque = |rgb: &[u8]| SagasColor { r: rgb[0], g: rgb[1], b: rgb[2], a: 255 };
+
+        let colors = match format {
+            PaletteFormat::Act => {
+                // 768 bytes of RGB, optionally followed by a big-endian entry
+                // count and transparent index.
+                let (table, count, transparent) = match data.len() {
+                    768 => (&data[..], 256, None),
+                    772 => {
+                        let count = u16::from_be_bytes([data[768], data[769]]) as usize;
+                        let transparent = u16::from_be_bytes([data[770], data[771]]) as usize;
+                        (&data[..768], count.clamp(1, 256), Some(transparent))
+                    },
+                    n => return Err(SagasError::BadPalette(format!("expected 768 or 772 bytes, got {}", n))),
+                };
+                let mut colors: Vec<_> = table.chunks(3).take(count).map(opaque).collect();
+                if let Some(c) = transparent.and_then(|i| colors.get_mut(i)) {
+                    c.a = 0;
+                }
+                colors
+            },
+            PaletteFormat::Pal => {
+                let text = String::from_utf8_lossy(&data);
+                let mut lines = text.lines().map(str::trim);
+                if lines.next() != Some("JASC-PAL") || lines.next() != Some("0100") {
+                    return Err(SagasError::BadPalette("missing JASC-PAL header".to_string()));
+                }
+                let count: usize = lines.next().and_then(|l| l.parse().ok())
+                    .ok_or_else(|| SagasError::BadPalette("missing entry count".to_string()))?;
+
+                let colors = lines.filter(|l| !l.is_empty())
+                    .map(|l| {
+                        let rgb = l.split_whitespace().map(str::parse).collect::<std::result::Result<Vec<u8>, _>>();
+                        match rgb {
+                            Ok(rgb) if rgb.len() == 3 => Ok(opaque(&rgb)),
+                            _ => Err(SagasError::BadPalette(format!("bad entry '{}'", l))),
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if colors.len() != count {
+                    return Err(SagasError::BadPalette(format!("header says {} entries, found {}", count, colors.len())));
+                }
+                colors
+            },
+            PaletteFormat::Gpl => {
+                return Err(SagasError::BadPalette("importing .gpl palettes isn't supported".to_string()));
+            },
+        };
+
+        Ok(SagasColorLUT {
+            colors,
+        })
+    }
+}
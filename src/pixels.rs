@@ -0,0 +1,17 @@
+// 4-bit images store two palette indices per byte, low nibble first (PS2 PSMT4).
+
+// Expands `packed` into `count` one-byte indices.
+pub fn unpack_4bpp(packed: &[u8], count: usize) -> Vec<u8> {
+    packed.iter()
+        .flat_map(|&b| [b & 0x0f, b >> 4])
+        .take(count)
+        .collect()
+}
+
+// Inverse of `unpack_4bpp`. Indices must be below 16, an odd count leaves the
+// last high nibble zero.
+pub fn pack_4bpp(indices: &[u8]) -> Vec<u8> {
+    indices.chunks(2)
+        .map(|pair| (pair[0] & 0x0f) | (pair.get(1).copied().unwrap_or(0) << 4))
+        .collect()
+}
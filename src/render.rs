@@ -0,0 +1,219 @@
+use image::{ImageBuffer, Luma, Rgb, Rgba, RgbImage, RgbaImage};
+use std::io::Write;
+
+use crate::{IndexImage, Result, SagasColorLUT, SagasError, SagasFile};
+
+// Blends `fg` over an opaque background color.
+fn blend_over(fg: Rgba<u8>, bg: [u8; 3]) -> Rgba<u8> {
+    let a = fg.0[3] as u32;
+    let mix = |f: u8, b: u8| ((f as u32 * a + b as u32 * (255 - a) + 127) / 255) as u8;
+    Rgba([mix(fg.0[0], bg[0]), mix(fg.0[1], bg[1]), mix(fg.0[2], bg[2]), 255])
+}
+
+impl SagasColorLUT {
+    // Looks up every index in the table, failing on the first one that's out of range.
+    pub fn render(&self, indices: &IndexImage) -> Result<RgbaImage> {
+        let palette_size = self.colors.len();
+        let mut image = RgbaImage::new(indices.width(), indices.height());
+        for (pixel, &Luma([index])) in image.pixels_mut().zip(indices.pixels()) {
+            let c = self.colors.get(index as usize)
+                .ok_or(SagasError::IndexOutOfRange { index, palette_size })?;
+            *pixel = Rgba(c.to_rgba());
+        }
+        Ok(image)
+    }
+
+    // Renders the table as a grid of 16 swatches per row, each `cell` pixels
+    // square. Alpha is shown over a checkerboard so transparent entries stand out.
+    pub fn swatch_image(&self, cell: u32) -> RgbaImage {
+        let columns = 16;
+        let rows = (self.colors.len() as u32).div_ceil(columns);
+        let checker = (cell / 2).max(1);
+
+        RgbaImage::from_fn(columns * cell, rows * cell, |x, y| {
+            let bg = if (x / checker + y / checker).is_multiple_of(2) { [0xcc; 3] } else { [0x88; 3] };
+            let i = ((y / cell) * columns + x / cell) as usize;
+            match self.colors.get(i) {
+                Some(c) => blend_over(Rgba(c.to_rgba()), bg),
+                None => Rgba([bg[0], bg[1], bg[2], 255]),
+            }
+        })
+    }
+}
+
+// Composites `image` over a solid `background`, for formats without alpha.
+pub fn flatten(image: &RgbaImage, background: [u8; 3]) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, _]) = blend_over(*image.get_pixel(x, y), background);
+        Rgb([r, g, b])
+    })
+}
+
+// Spreads color into fully transparent pixels up to `radius` pixels away from
+// any visible one, keeping their alpha at 0. Each pass fills pixels touching
+// an already filled one with the average of those neighbours, so filtering
+// the image later doesn't pull in whatever color transparent pixels had.
+pub fn edge_bleed(image: &mut RgbaImage, radius: u32) {
+    let (width, height) = image.dimensions();
+    let mut filled: Vec<bool> = image.pixels().map(|p| p.0[3] != 0).collect();
+
+    for _ in 0..radius {
+        let mut updates = Vec::new();
+        for (x, y, _) in image.enumerate_pixels().filter(|&(x, y, _)| !filled[(y * width + x) as usize]) {
+            let mut sum = [0u32; 3];
+            let mut count = 0;
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    if filled[(ny * width + nx) as usize] {
+                        let p = image.get_pixel(nx, ny).0;
+                        for (s, v) in sum.iter_mut().zip(p) {
+                            *s += v as u32;
+                        }
+                        count += 1;
+                    }
+                }
+            }
+            if count > 0 {
+                let [r, g, b] = sum.map(|s| ((s + count / 2) / count) as u8);
+                updates.push((x, y, Rgba([r, g, b, 0])));
+            }
+        }
+        if updates.is_empty() {
+            break;
+        }
+        for (x, y, pixel) in updates {
+            image.put_pixel(x, y, pixel);
+            filled[(y * width + x) as usize] = true;
+        }
+    }
+}
+
+// 16 bits per channel RGBA, for --depth16.
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+// Raises the RGB channels (as 0-1 values) to the power `gamma`, leaving alpha
+// alone. 1 keeps the colors as they are, 2.2 roughly linearizes sRGB.
+pub fn apply_gamma(image: &mut RgbaImage, gamma: f32) {
+    let table: Vec<u8> = (0..=255u8).map(|v| ((v as f32 / 255.0).powf(gamma) * 255.0).round() as u8).collect();
+    for Rgba([r, g, b, _]) in image.pixels_mut() {
+        for c in [r, g, b] {
+            *c = table[*c as usize];
+        }
+    }
+}
+
+// Widens `image` to 16 bits per channel, applying `gamma` like `apply_gamma`
+// but keeping the extra precision. Alpha is only widened.
+pub fn to_rgba16(image: &RgbaImage, gamma: f32) -> Rgba16Image {
+    let table: Vec<u16> = (0..=255u8).map(|v| ((v as f32 / 255.0).powf(gamma) * 65535.0).round() as u16).collect();
+    Rgba16Image::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+        Rgba([table[r as usize], table[g as usize], table[b as usize], a as u16 * 257])
+    })
+}
+
+// Writes a 16 bits per channel RGBA PNG.
+pub fn write_png16<W: Write>(image: &Rgba16Image, w: W) -> Result<()> {
+    let mut encoder = png::Encoder::new(w, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Sixteen);
+
+    // PNG samples are big endian.
+    let data: Vec<u8> = image.as_raw().iter().flat_map(|v| v.to_be_bytes()).collect();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+// The channels of `image` one after another: every red value, then green,
+// blue and, with `alpha`, alpha, each plane row major. This is what
+// Photoshop calls a non-interleaved RAW.
+pub fn to_planar(image: &RgbaImage, alpha: bool) -> Vec<u8> {
+    let channels = if alpha { 4 } else { 3 };
+    let pixels = image.as_raw();
+    (0..channels).flat_map(|c| pixels.iter().skip(c).step_by(4).copied()).collect()
+}
+
+// Writes `image` as an OpenEXR file of linear light 32-bit float RGBA,
+// converting like `SagasColor::to_linear_rgba`. Alpha stays straight.
+#[cfg(feature = "exr")]
+pub fn write_linear_exr<W: Write + std::io::Seek>(image: &RgbaImage, w: W) -> Result<()> {
+    use crate::SagasColor;
+    use exr::prelude::{Image, SpecificChannels, Vec2, WritableImage};
+
+    let channels = SpecificChannels::rgba(|Vec2(x, y): Vec2<usize>| {
+        let [r, g, b, a] = image.get_pixel(x as u32, y as u32).0;
+        let [r, g, b, a] = SagasColor { r, g, b, a }.to_linear_rgba();
+        (r, g, b, a)
+    });
+    Image::from_channels((image.width() as usize, image.height() as usize), channels)
+        .write()
+        .to_buffered(w)?;
+    Ok(())
+}
+
+impl SagasFile {
+    // Calls `f` with each row number and its colors, top to bottom, looking
+    // colors up one row at a time instead of rendering the whole image. Stops
+    // at the first index that's out of range for the palette.
+    pub fn decode_rows<F>(&self, mut f: F) -> Result<()>
+        where F : FnMut(u32, &[Rgba<u8>])
+    {
+        let colors = &self.get_color_table().colors;
+        let palette_size = colors.len();
+        let width = self.get_header().width as usize;
+
+        let mut row = Vec::with_capacity(width);
+        for (y, indices) in self.get_image().chunks_exact(width).enumerate() {
+            row.clear();
+            for &index in indices {
+                let c = colors.get(index as usize)
+                    .ok_or(SagasError::IndexOutOfRange { index, palette_size })?;
+                row.push(Rgba(c.to_rgba()));
+            }
+            f(y as u32, &row);
+        }
+        Ok(())
+    }
+}
+
+impl SagasFile {
+    // Positions of the pixels whose color differs between the two files, in
+    // row major order, or None if the images aren't the same size. Colors are
+    // compared after the palette lookup, so the stored bytes (header padding,
+    // palette order) don't matter. Indices out of range on both sides only
+    // match if they're the same index.
+    pub fn differing_pixels<'a>(&'a self, other: &'a SagasFile) -> Option<impl Iterator<Item = (u32, u32)> + 'a> {
+        let (a, b) = (self.get_header(), other.get_header());
+        if (a.width, a.height) != (b.width, b.height) {
+            return None;
+        }
+        let width = a.width as usize;
+        let color = |sf: &SagasFile, i: u8| sf.get_color_table().colors.get(i as usize).map(|c| c.to_rgba()).ok_or(i);
+        Some(self.get_image().iter().zip(other.get_image())
+            .enumerate()
+            .filter(move |&(_, (&i, &j))| color(self, i) != color(other, j))
+            .map(move |(n, _)| ((n % width) as u32, (n / width) as u32)))
+    }
+
+    // Whether both files render to exactly the same RGBA image.
+    pub fn visually_eq(&self, other: &SagasFile) -> bool {
+        self.differing_pixels(other).is_some_and(|mut pixels| pixels.next().is_none())
+    }
+}
+
+impl TryFrom<&SagasFile> for RgbaImage {
+    type Error = SagasError;
+
+    fn try_from(sf: &SagasFile) -> Result<Self> {
+        let header = sf.get_header();
+        let mut image = RgbaImage::new(header.width as u32, header.height as u32);
+        sf.decode_rows(|y, row| {
+            for (x, &pixel) in row.iter().enumerate() {
+                image.put_pixel(x as u32, y, pixel);
+            }
+        })?;
+        Ok(image)
+    }
+}
@@ -0,0 +1,110 @@
+use image::{imageops::{self, FilterType}, Rgba, RgbaImage};
+
+const BACKGROUND: Rgba<u8> = Rgba([0x20, 0x20, 0x20, 255]);
+const LABEL_COLOR: Rgba<u8> = Rgba([0xee, 0xee, 0xee, 255]);
+const GAP: u32 = 4; // between cells and around the sheet
+
+// 3x5 pixel glyphs, one row per byte with the leftmost pixel in bit 2.
+// Lowercase letters use the uppercase ones, anything else shows as `?`.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        ' ' => [0; 5],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+// Draws `text` with its top left corner at (x, y), each font pixel `scale`
+// pixels square. Characters that don't fit in `max_width` are cut off.
+fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32, scale: u32, max_width: u32) {
+    let advance = 4 * scale;
+    for (i, c) in text.chars().take((max_width / advance) as usize).enumerate() {
+        let left = x + i as u32 * advance;
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (left + column * scale + dx, y + row as u32 * scale + dy);
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, LABEL_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// `image` scaled with nearest neighbor to fit in a `size` pixel square,
+// keeping its aspect ratio. Images that already fit are scaled up.
+pub fn fit_nearest(image: &RgbaImage, size: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let factor = f64::min(size as f64 / width as f64, size as f64 / height as f64);
+    let scaled = |v: u32| ((v as f64 * factor).round() as u32).clamp(1, size);
+    imageops::resize(image, scaled(width), scaled(height), FilterType::Nearest)
+}
+
+// Tiles `cells` into a grid of `columns` columns, each image fitted and
+// centered in a `cell` pixel square. With `labels` every cell gets its
+// label written underneath it.
+pub fn contact_sheet(cells: &[(RgbaImage, String)], columns: u32, cell: u32, labels: bool) -> RgbaImage {
+    let columns = columns.clamp(1, (cells.len() as u32).max(1));
+    let rows = (cells.len() as u32).div_ceil(columns);
+    let scale = if cell >= 96 { 2 } else { 1 };
+    let label_height = if labels { 6 * scale + GAP } else { 0 };
+    let (cell_width, cell_height) = (cell + GAP, cell + label_height + GAP);
+
+    let mut sheet = RgbaImage::from_pixel(GAP + columns * cell_width, GAP + rows * cell_height, BACKGROUND);
+    for (i, (image, label)) in cells.iter().enumerate() {
+        let (x, y) = (GAP + i as u32 % columns * cell_width, GAP + i as u32 / columns * cell_height);
+        let thumbnail = fit_nearest(image, cell);
+        let (dx, dy) = ((cell - thumbnail.width()) / 2, (cell - thumbnail.height()) / 2);
+        imageops::overlay(&mut sheet, &thumbnail, (x + dx) as i64, (y + dy) as i64);
+        if labels {
+            draw_text(&mut sheet, label, x, y + cell + GAP, scale, cell);
+        }
+    }
+    sheet
+}
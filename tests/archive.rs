@@ -0,0 +1,45 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::Fixture;
+use dbz_sagas_extractor::{FromReader, SagasArchive, SagasError, SagasFile};
+
+const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+
+#[test]
+fn single_file_is_an_archive_of_one() {
+    let archive = SagasArchive::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    assert_eq!(archive.files.len(), 1);
+    assert_eq!(archive.files[0].record_len().unwrap(), RADITZ.len() as u64);
+}
+
+#[test]
+fn reads_concatenated_files() {
+    let mut small = Fixture::new(4, 2);
+    small.image = vec![7; 8];
+    let small = small.build();
+
+    let mut data = RADITZ.to_vec();
+    data.extend_from_slice(&small);
+    // The next file starts on a 16 byte boundary.
+    data.resize(data.len().next_multiple_of(16), 0);
+    data.extend_from_slice(&small);
+    data.extend_from_slice(b"trailing garbage");
+
+    let archive = SagasArchive::from_reader(&mut Cursor::new(data)).unwrap();
+    assert_eq!(archive.files.len(), 3);
+
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    assert_eq!(archive.files[0].get_image(), raditz.get_image());
+    for sf in &archive.files[1..] {
+        assert_eq!((sf.get_header().width, sf.get_header().height), (4, 2));
+        assert_eq!(sf.get_image(), &[7; 8]);
+    }
+}
+
+#[test]
+fn first_file_must_have_a_signature() {
+    let result = SagasArchive::from_reader(&mut Cursor::new(b"not a portrait at all".to_vec()));
+    assert!(matches!(result, Err(SagasError::BadSignature)));
+}
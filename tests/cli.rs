@@ -0,0 +1,715 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::Fixture;
+
+const EXE: &str = env!("CARGO_BIN_EXE_dbz-sagas-extractor");
+const RADITZ: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/raditz.dbl");
+
+// A fresh directory under the system temp dir, removed again on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("sagas-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn creates_missing_output_directories() {
+    let tmp = TempDir::new("mkdir");
+    let output = tmp.0.join("does/not/exist/raditz.png");
+
+    let status = Command::new(EXE).args(["-q", "-i", RADITZ, "-o"]).arg(&output).status().unwrap();
+    assert!(status.success());
+    let image = image::open(&output).unwrap();
+    assert_eq!((image.width(), image.height()), (128, 128));
+}
+
+#[test]
+fn reports_directories_that_cant_be_created() {
+    let tmp = TempDir::new("mkdir-fail");
+    // A file where a directory is needed.
+    let blocker = tmp.0.join("blocker");
+    fs::write(&blocker, b"").unwrap();
+
+    let output = Command::new(EXE).args(["-i", RADITZ, "-o"]).arg(blocker.join("raditz.png")).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not create"), "{}", stderr);
+    assert!(stderr.contains("blocker"), "{}", stderr);
+}
+
+#[test]
+fn out_of_range_indices_are_filled_unless_strict() {
+    let tmp = TempDir::new("oob");
+    let mut fixture = Fixture::new(4, 4);
+    fixture.clut = (8, 2);
+    fixture.palette.truncate(16);
+    fixture.image = vec![1; 16];
+    fixture.image[5] = 200;
+    let input = tmp.0.join("stray.dbl");
+    fs::write(&input, fixture.build()).unwrap();
+
+    let output = tmp.0.join("stray.png");
+    let run = |extra: &[&str]| {
+        Command::new(EXE).arg("-q").arg("-i").arg(&input).arg("-o").arg(&output).args(extra).status().unwrap()
+    };
+
+    assert!(run(&["--oob-color", "1,2,3,255"]).success());
+    let image = image::open(&output).unwrap().to_rgba8();
+    assert_eq!(image.get_pixel(1, 1).0, [1, 2, 3, 255]);
+    assert_ne!(image.get_pixel(0, 0).0, [1, 2, 3, 255]);
+
+    assert!(run(&["--oob-color", "#04050680"]).success());
+    assert_eq!(image::open(&output).unwrap().to_rgba8().get_pixel(1, 1).0, [4, 5, 6, 0x80]);
+
+    assert!(!run(&["--strict"]).success());
+    let verify = Command::new(EXE).arg("--verify").arg("-i").arg(&input).output().unwrap();
+    assert!(!verify.status.success());
+    assert!(String::from_utf8_lossy(&verify.stdout).starts_with("FAIL"));
+}
+
+#[test]
+fn autocrop_trims_transparent_borders() {
+    let tmp = TempDir::new("autocrop");
+    let mut fixture = Fixture::new(8, 8);
+    fixture.clut = (8, 2);
+    fixture.palette = vec![[0xff, 0xff, 0xff, 0x80]; 16];
+    fixture.palette[0][3] = 0;
+    fixture.image = vec![0; 64];
+    fixture.image[2 * 8 + 3] = 1;
+    fixture.image[4 * 8 + 4] = 1;
+    let input = tmp.0.join("sprite.dbl");
+    fs::write(&input, fixture.build()).unwrap();
+
+    let output = tmp.0.join("sprite.png");
+    let manifest = tmp.0.join("manifest.csv");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&input).arg("-o").arg(&output)
+        .args(["--autocrop-padding", "1", "--manifest"]).arg(&manifest).status().unwrap();
+    assert!(status.success());
+    assert_eq!(image::open(&output).unwrap().to_rgba8().dimensions(), (4, 5));
+    let csv = fs::read_to_string(&manifest).unwrap();
+    assert!(csv.lines().next().unwrap().ends_with("offset_x,offset_y"), "{}", csv);
+    assert!(csv.lines().nth(1).unwrap().ends_with(",2,1"), "{}", csv);
+}
+
+#[test]
+fn verify_warns_about_odd_dimensions() {
+    let tmp = TempDir::new("verify-warn");
+    let input = tmp.0.join("wide.dbl");
+    fs::write(&input, Fixture::new(12, 8).build()).unwrap();
+
+    let output = Command::new(EXE).arg("--verify").arg("-i").arg(&input).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("PASS"), "{}", stdout);
+    assert!(stdout.contains("WARN") && stdout.contains("width 12 is not a power of two"), "{}", stdout);
+}
+
+#[test]
+fn json_summary_reports_the_whole_run() {
+    let tmp = TempDir::new("json-summary");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(RADITZ, inputs.join("raditz.dbl")).unwrap();
+    fs::write(inputs.join("broken.dbl"), b"not a portrait").unwrap();
+
+    let summary = tmp.0.join("summary.json");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&inputs).arg("-o").arg(tmp.0.join("out"))
+        .arg("--json-summary").arg(&summary).status().unwrap();
+    assert!(!status.success());
+
+    let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    assert_eq!(summary["total"], 2);
+    assert_eq!(summary["succeeded"], 1);
+    assert_eq!(summary["skipped"], 0);
+    assert_eq!(summary["failed"], 1);
+    assert!(summary["errors"][0]["input"].as_str().unwrap().ends_with("broken.dbl"));
+    let written = fs::metadata(tmp.0.join("out/raditz.png")).unwrap().len();
+    assert_eq!(summary["bytes_written"], written);
+}
+
+#[test]
+fn colliding_header_names_get_a_suffix() {
+    let tmp = TempDir::new("collide");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(RADITZ, inputs.join("a.dbl")).unwrap();
+    fs::copy(RADITZ, inputs.join("b.dbl")).unwrap();
+
+    let out = tmp.0.join("out");
+    let output = Command::new(EXE).arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .args(["--name-from-header", "--jobs", "2"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(out.join("fac.png").exists());
+    assert!(out.join("fac_1.png").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("already used by another input"), "{}", stderr);
+}
+
+#[test]
+fn palette_only_skips_the_image() {
+    let tmp = TempDir::new("palette-only");
+    let output = tmp.0.join("raditz.png");
+    let status = Command::new(EXE).args(["-q", "-i", RADITZ, "-o"]).arg(&output)
+        .args(["--palette-only", "--export-palette", "act"]).status().unwrap();
+    assert!(status.success());
+    assert!(!output.exists());
+    assert_eq!(fs::metadata(tmp.0.join("raditz.act")).unwrap().len(), 768);
+
+    let status = Command::new(EXE).args(["-q", "-i", RADITZ, "-o"]).arg(&output).arg("--palette-only").output().unwrap().status;
+    assert!(!status.success());
+}
+
+#[test]
+fn contact_sheet_tiles_a_directory() {
+    let tmp = TempDir::new("contact-sheet");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(RADITZ, inputs.join("a.dbl")).unwrap();
+    fs::copy(RADITZ, inputs.join("b.dbl")).unwrap();
+
+    let sheet = tmp.0.join("sheet.png");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&inputs).arg("-o").arg(tmp.0.join("out"))
+        .arg("--contact-sheet").arg(&sheet).args(["--cell-size", "64", "--sheet-labels"]).status().unwrap();
+    assert!(status.success());
+    assert_eq!(image::open(&sheet).unwrap().to_rgba8().dimensions(), (140, 82));
+}
+
+#[test]
+fn list_prints_aligned_columns() {
+    let tmp = TempDir::new("list");
+    fs::copy(RADITZ, tmp.0.join("raditz.dbl")).unwrap();
+    fs::write(tmp.0.join("wide.dbl"), Fixture::new(16, 8).build()).unwrap();
+
+    let output = Command::new(EXE).arg("--list").arg("-i").arg(&tmp.0).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].ends_with("raditz.dbl  128x128  256  c:\\dev\\dbz\\data_ps2\\sprites\\fac"), "{}", stdout);
+    assert!(lines[1].ends_with("wide.dbl       16x8  256  c:\\dev\\dbz\\data_ps2\\sprites\\test"), "{}", stdout);
+    assert!(!tmp.0.join("raditz.png").exists());
+}
+
+#[test]
+fn output_template_names_the_outputs() {
+    let tmp = TempDir::new("template");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(RADITZ, inputs.join("raditz.dbl")).unwrap();
+
+    let out = tmp.0.join("out");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .args(["--output-template", "{name}/{stem}_{width}x{height}_{index}.png"]).status().unwrap();
+    assert!(status.success());
+    assert!(out.join("fac/raditz_128x128_0.png").exists());
+
+    let output = Command::new(EXE).arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .args(["--output-template", "{stem}_{size}.png"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown placeholder '{size}'"));
+}
+
+#[test]
+fn compare_reports_the_first_difference() {
+    let tmp = TempDir::new("compare");
+    let changed = tmp.0.join("changed.dbl");
+    let mut data = fs::read(RADITZ).unwrap();
+    data[0x4a0 + 2 * 128 + 3] ^= 1;
+    fs::write(&changed, data).unwrap();
+
+    let same = Command::new(EXE).args(["--compare", RADITZ, RADITZ]).output().unwrap();
+    assert!(same.status.success());
+    assert!(String::from_utf8_lossy(&same.stdout).contains("render identically"));
+
+    let differ = Command::new(EXE).args(["--compare", RADITZ]).arg(&changed).output().unwrap();
+    assert_eq!(differ.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&differ.stdout);
+    assert!(stdout.contains("differ in 1 pixel, the first at (3, 2)"), "{}", stdout);
+}
+
+#[test]
+fn zip_collects_the_outputs() {
+    let tmp = TempDir::new("zip");
+    let inputs = tmp.0.join("in");
+    fs::create_dir_all(inputs.join("sub")).unwrap();
+    fs::copy(RADITZ, inputs.join("a.dbl")).unwrap();
+    fs::copy(RADITZ, inputs.join("sub/b.dbl")).unwrap();
+
+    let out = tmp.0.join("out");
+    let archive = tmp.0.join("portraits.zip");
+    let status = Command::new(EXE).arg("-q").arg("-r").arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .arg("--zip").arg(&archive).status().unwrap();
+    assert!(status.success());
+    assert!(!out.exists());
+
+    let mut zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+    let mut names: Vec<_> = zip.file_names().map(str::to_string).collect();
+    names.sort();
+    assert_eq!(names, ["a.png", "sub/b.png"]);
+    let mut png = Vec::new();
+    std::io::Read::read_to_end(&mut zip.by_name("sub/b.png").unwrap(), &mut png).unwrap();
+    assert_eq!(image::load_from_memory(&png).unwrap().width(), 128);
+}
+
+#[test]
+fn limit_counts_successful_inputs() {
+    let tmp = TempDir::new("limit");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::write(inputs.join("a_broken.dbl"), b"not a portrait").unwrap();
+    fs::copy(RADITZ, inputs.join("b.dbl")).unwrap();
+    fs::copy(RADITZ, inputs.join("c.dbl")).unwrap();
+
+    let out = tmp.0.join("out");
+    let summary = tmp.0.join("summary.json");
+    let output = Command::new(EXE).arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .args(["--limit", "1", "--json-summary"]).arg(&summary).output().unwrap();
+    assert!(!output.status.success());
+    assert!(out.join("b.png").exists());
+    assert!(!out.join("c.png").exists());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 inputs were not processed"));
+
+    let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    assert_eq!((summary["succeeded"].as_u64(), summary["failed"].as_u64()), (Some(1), Some(1)));
+    assert_eq!(summary["truncated"], true);
+    assert_eq!(summary["not_processed"], 1);
+}
+
+#[test]
+fn select_and_exclude_filter_the_walk() {
+    let tmp = TempDir::new("select");
+    fs::create_dir(tmp.0.join("sub")).unwrap();
+    fs::copy(RADITZ, tmp.0.join("raditz.bin")).unwrap();
+    fs::copy(RADITZ, tmp.0.join("sub/raditz.bin")).unwrap();
+    fs::copy(RADITZ, tmp.0.join("sub/skip.bin")).unwrap();
+    // Never parsed, it would fail the run otherwise.
+    fs::write(tmp.0.join("notes.txt"), "not a sagas file").unwrap();
+
+    let output = Command::new(EXE).args(["--list", "-r", "--select", "*.bin", "--exclude", "sub/s*"]).arg("-i").arg(&tmp.0)
+        .output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].starts_with(&format!("{}  ", tmp.0.join("raditz.bin").display())), "{}", stdout);
+    assert!(lines[1].starts_with(&format!("{}  ", tmp.0.join("sub/raditz.bin").display())), "{}", stdout);
+}
+
+#[test]
+fn raw_planar_writes_planes_and_layout() {
+    let tmp = TempDir::new("raw-planar");
+    let output = tmp.0.join("raditz.raw");
+    let status = Command::new(EXE).args(["-q", "--raw-planar", "-i", RADITZ, "-o"]).arg(&output).status().unwrap();
+    assert!(status.success());
+
+    let raw = fs::read(&output).unwrap();
+    assert_eq!(raw.len(), 128 * 128 * 4);
+    let expected = image::RgbaImage::try_from(&dbz_sagas_extractor::parse_file(RADITZ).unwrap()).unwrap();
+    let (plane, i) = (128 * 128, 7 * 128 + 5);
+    assert_eq!([raw[i], raw[plane + i], raw[2 * plane + i], raw[3 * plane + i]], expected.get_pixel(5, 7).0);
+
+    let info: serde_json::Value = serde_json::from_slice(&fs::read(tmp.0.join("raditz.json")).unwrap()).unwrap();
+    assert_eq!(info["width"], 128);
+    assert_eq!(info["channels"], "RGBA");
+}
+
+#[test]
+fn timings_are_printed_per_input_and_overall() {
+    let tmp = TempDir::new("timings");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(RADITZ, inputs.join("a.dbl")).unwrap();
+    fs::copy(RADITZ, inputs.join("b.dbl")).unwrap();
+
+    let output = Command::new(EXE).args(["-q", "--timings", "-i"]).arg(&inputs).arg("-o").arg(tmp.0.join("out"))
+        .output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stderr);
+    assert!(lines[0].contains("a.dbl: decode ") && lines[0].ends_with(" ms"), "{}", stderr);
+    assert!(lines[1].contains("b.dbl: decode "), "{}", stderr);
+    assert!(lines[2].starts_with("timing: decode min ") && lines[2].contains(", p95 "), "{}", stderr);
+    assert!(lines[3].starts_with("timing: save min "), "{}", stderr);
+}
+
+#[test]
+fn checksums_are_written_and_verified() {
+    let tmp = TempDir::new("checksums");
+    let inputs = tmp.0.join("in");
+    fs::create_dir_all(inputs.join("sub")).unwrap();
+    fs::copy(RADITZ, inputs.join("a.dbl")).unwrap();
+    fs::copy(RADITZ, inputs.join("sub/b.dbl")).unwrap();
+    let manifest = tmp.0.join("checksums.txt");
+
+    let run = |flag: &str| Command::new(EXE).args(["-r", flag]).arg(&manifest).arg("-i").arg(&inputs).output().unwrap();
+    assert!(run("--write-checksums").status.success());
+    let written = fs::read_to_string(&manifest).unwrap();
+    let lines: Vec<_> = written.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", written);
+    assert!(lines[0].ends_with("  a.dbl") && lines[1].ends_with("  sub/b.dbl"), "{}", written);
+    assert_eq!(lines[0].split("  ").next(), lines[1].split("  ").next());
+
+    let output = run("--verify-checksums");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "PASS a.dbl\nPASS sub/b.dbl\n");
+
+    // Only differs in a field the hash leaves out.
+    let mut data = fs::read(RADITZ).unwrap();
+    data[0x10] ^= 1;
+    fs::write(inputs.join("a.dbl"), &data).unwrap();
+    assert!(run("--verify-checksums").status.success());
+
+    data[0x4a0] ^= 1;
+    fs::write(inputs.join("a.dbl"), &data).unwrap();
+    fs::rename(inputs.join("sub/b.dbl"), inputs.join("c.dbl")).unwrap();
+    let output = run("--verify-checksums");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("MISMATCH a.dbl: expected "), "{}", stdout);
+    assert!(stdout.contains("\nEXTRA c.dbl\n") && stdout.ends_with("\nMISSING sub/b.dbl\n"), "{}", stdout);
+}
+
+#[test]
+fn subcommands_and_the_old_flags() {
+    let tmp = TempDir::new("subcommands");
+    let png = tmp.0.join("raditz.png");
+
+    let status = Command::new(EXE).args(["extract", "-q", "-i", RADITZ, "-o"]).arg(&png).status().unwrap();
+    assert!(status.success());
+    assert!(png.exists());
+
+    // Shared flags also go before the subcommand.
+    let info = Command::new(EXE).args(["-i", RADITZ, "info"]).output().unwrap();
+    assert!(info.status.success());
+    assert!(String::from_utf8_lossy(&info.stdout).contains("128x128  256"));
+    let hash = Command::new(EXE).args(["info", "--hash", "-i", RADITZ]).output().unwrap();
+    assert!(String::from_utf8_lossy(&hash.stdout).ends_with("raditz.dbl\n"));
+
+    let status = Command::new(EXE).args(["palette", "-q", "--palette-image", "-i", RADITZ, "-o"]).arg(&png).status().unwrap();
+    assert!(status.success());
+    assert!(tmp.0.join("raditz_palette.png").exists());
+
+    let dbl = tmp.0.join("raditz.dbl");
+    let status = Command::new(EXE).args(["encode", "-i"]).arg(&png).arg("-o").arg(&dbl).status().unwrap();
+    assert!(status.success());
+    assert_eq!(dbz_sagas_extractor::parse_file(&dbl).unwrap().get_header().width, 128);
+
+    // Flags only belong to their own subcommand.
+    let wrong = Command::new(EXE).args(["extract", "--hash", "-i", RADITZ]).output().unwrap();
+    assert!(!wrong.status.success());
+
+    // Without a subcommand everything still works, with a warning.
+    let old = Command::new(EXE).args(["--hash", "-i", RADITZ]).output().unwrap();
+    assert!(old.status.success());
+    assert_eq!(old.stdout, hash.stdout);
+    assert!(String::from_utf8_lossy(&old.stderr).contains("deprecated"));
+}
+
+#[test]
+fn alpha_histogram_suggests_an_alpha_mode() {
+    let histogram = |input: &std::path::Path| {
+        let output = Command::new(EXE).args(["info", "--alpha-histogram", "-i"]).arg(input).output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    let raditz = histogram(RADITZ.as_ref());
+    assert_eq!(raditz["pixels"], 128 * 128);
+    assert_eq!(raditz["counts"]["128"], 8031);
+    assert_eq!(raditz["counts"]["255"], 0);
+    assert_eq!(raditz["suggested_alpha_mode"], "double");
+
+    let tmp = TempDir::new("alpha-histogram");
+    let mut fixture = Fixture::new(4, 4);
+    fixture.palette = (0..=255u8).map(|i| [i, i, i, i]).collect();
+    fixture.image = (0..16).map(|i| i * 17).collect();
+    let input = tmp.0.join("full-range.dbl");
+    fs::write(&input, fixture.build()).unwrap();
+    let full = histogram(&input);
+    assert_eq!(full["counts"]["0"], 1);
+    assert_eq!(full["suggested_alpha_mode"], "raw");
+}
+
+#[test]
+fn alpha_histogram_counts_out_of_range_pixels_apart() {
+    let tmp = TempDir::new("alpha-histogram-oob");
+    let mut fixture = Fixture::new(4, 4);
+    fixture.clut = (8, 2);
+    fixture.palette = vec![[0, 0, 0, 0x80]; 16];
+    fixture.image = vec![0; 16];
+    fixture.image[3] = 200;
+    let input = tmp.0.join("stray.dbl");
+    fs::write(&input, fixture.build()).unwrap();
+
+    let output = Command::new(EXE).args(["info", "--alpha-histogram", "-i"]).arg(&input).output().unwrap();
+    assert!(output.status.success());
+    let histogram: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(histogram["pixels"], 16);
+    assert_eq!(histogram["counts"]["128"], 15);
+    assert_eq!(histogram["counts"]["255"], 0);
+    assert_eq!(histogram["out_of_range"], 1);
+    assert_eq!(histogram["suggested_alpha_mode"], "double");
+}
+
+#[test]
+fn index_map_holds_the_palette_indices() {
+    let tmp = TempDir::new("index-map");
+    let output = tmp.0.join("raditz.png");
+    let status = Command::new(EXE).args(["extract", "-q", "--index-map", "-i", RADITZ, "-o"]).arg(&output).status().unwrap();
+    assert!(status.success());
+
+    let map = image::open(&output).unwrap().to_rgba8();
+    let indices = dbz_sagas_extractor::parse_file(RADITZ).unwrap().index_image();
+    assert!(map.enumerate_pixels().all(|(x, y, p)| p.0 == [indices.get_pixel(x, y).0[0], 0, 0, 255]));
+}
+
+#[test]
+fn route_by_header_groups_outputs_into_subdirectories() {
+    let tmp = TempDir::new("route-by-header");
+    let inputs = tmp.0.join("in");
+    fs::create_dir(&inputs).unwrap();
+    fs::copy(RADITZ, inputs.join("raditz.dbl")).unwrap();
+    let out = tmp.0.join("out");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .args(["--route-by-header", "string0"]).status().unwrap();
+    assert!(status.success());
+    assert!(out.join("sprites/raditz.png").exists());
+
+    let mut fixture = Fixture::new(4, 4);
+    fixture.texture = "a:b_000.tga";
+    fs::write(inputs.join("colon.dbl"), fixture.build()).unwrap();
+    fixture.texture = ".._000.tga";
+    fs::write(inputs.join("dots.dbl"), fixture.build()).unwrap();
+    let out = tmp.0.join("out1");
+    let output = Command::new(EXE).arg("-i").arg(&inputs).arg("-o").arg(&out)
+        .args(["--route-by-header", "string1"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(out.join("Bit08/raditz.png").exists());
+    assert!(out.join("a_b/colon.png").exists());
+    assert!(out.join("dots.png").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no usable subdirectory"), "{}", stderr);
+
+    let status = Command::new(EXE).args(["-q", "-i", RADITZ, "--route-by-header", "string2"]).status().unwrap();
+    assert!(!status.success());
+}
+
+#[test]
+fn zip_inputs_are_extracted_like_directories() {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let tmp = TempDir::new("zip-input");
+    let archive = tmp.0.join("rip.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    let deflated = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("chars/raditz.dbl", deflated).unwrap();
+    zip.write_all(&fs::read(RADITZ).unwrap()).unwrap();
+    zip.start_file("small.dbl", deflated).unwrap();
+    zip.write_all(&Fixture::new(4, 4).build()).unwrap();
+    zip.start_file("readme.txt", deflated).unwrap();
+    zip.write_all(b"ripped from the PS2 disc").unwrap();
+    zip.finish().unwrap();
+
+    let out = tmp.0.join("out");
+    let output = Command::new(EXE).arg("-i").arg(&archive).arg("-o").arg(&out).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2 succeeded, 0 skipped, 0 failed.");
+    let image = image::open(out.join("chars/raditz.png")).unwrap();
+    assert_eq!((image.width(), image.height()), (128, 128));
+    assert!(out.join("small.png").exists());
+    assert!(!out.join("readme.png").exists());
+
+    let out = tmp.0.join("selected");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&archive).arg("-o").arg(&out)
+        .args(["--select", "chars/*"]).status().unwrap();
+    assert!(status.success());
+    assert!(out.join("chars/raditz.png").exists());
+    assert!(!out.join("small.png").exists());
+}
+
+#[test]
+fn huge_zip_entries_are_skipped() {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let tmp = TempDir::new("zip-huge");
+    let archive = tmp.0.join("rip.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    let deflated = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("raditz.dbl", deflated).unwrap();
+    zip.write_all(&fs::read(RADITZ).unwrap()).unwrap();
+    // A valid start padded past the 64 MiB limit, compresses to almost nothing.
+    zip.start_file("huge.dbl", deflated.large_file(true)).unwrap();
+    zip.write_all(&Fixture::new(4, 4).build()).unwrap();
+    let zeros = vec![0; 1 << 20];
+    for _ in 0..65 {
+        zip.write_all(&zeros).unwrap();
+    }
+    zip.finish().unwrap();
+
+    let out = tmp.0.join("out");
+    let output = Command::new(EXE).arg("-i").arg(&archive).arg("-o").arg(&out).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "1 succeeded, 0 skipped, 0 failed.");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("skipping huge.dbl, it's larger than"), "{}", stderr);
+    assert!(!out.join("huge.png").exists());
+}
+
+#[test]
+fn split_atlas_writes_every_cell() {
+    let tmp = TempDir::new("split-atlas");
+    let mut fixture = Fixture::new(8, 8);
+    fixture.clut = (8, 2);
+    fixture.palette = vec![[0, 0, 0, 0]; 16];
+    fixture.palette[1] = [0xff, 0, 0, 0x80];
+    fixture.palette[2] = [0, 0xff, 0, 0x80];
+    fixture.image = vec![0; 64];
+    fixture.image[8 + 1] = 1;     // (1, 1), top left cell
+    fixture.image[5 * 8 + 6] = 2; // (6, 5), bottom right cell
+    let input = tmp.0.join("sheet.dbl");
+    fs::write(&input, fixture.build()).unwrap();
+
+    let output = tmp.0.join("out/sheet.png");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&input).arg("-o").arg(&output)
+        .args(["--split-atlas", "2,2"]).status().unwrap();
+    assert!(status.success());
+    let cell = |name: &str| image::open(tmp.0.join("out").join(name)).unwrap().to_rgba8();
+    for name in ["sheet_r0_c0.png", "sheet_r0_c1.png", "sheet_r1_c0.png", "sheet_r1_c1.png"] {
+        assert_eq!(cell(name).dimensions(), (4, 4), "{}", name);
+    }
+    assert_eq!(cell("sheet_r0_c0.png").get_pixel(1, 1).0, [0xff, 0, 0, 0xff]);
+    assert_eq!(cell("sheet_r1_c1.png").get_pixel(2, 1).0, [0, 0xff, 0, 0xff]);
+    assert!(!output.exists());
+
+    // One palette for the whole sheet, not one per cell.
+    let output = tmp.0.join("palette/sheet.png");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&input).arg("-o").arg(&output)
+        .args(["--split-atlas", "2,2", "--palette-image", "--export-palette", "act"]).status().unwrap();
+    assert!(status.success());
+    let mut names: Vec<_> = fs::read_dir(tmp.0.join("palette")).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["sheet.act", "sheet_palette.png", "sheet_r0_c0.png", "sheet_r0_c1.png", "sheet_r1_c0.png", "sheet_r1_c1.png"]);
+
+    // --autocrop trims every cell on its own.
+    let output = tmp.0.join("cropped/sheet.png");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&input).arg("-o").arg(&output)
+        .args(["--split-atlas", "2,2", "--autocrop"]).status().unwrap();
+    assert!(status.success());
+    assert_eq!(image::open(tmp.0.join("cropped/sheet_r0_c0.png")).unwrap().to_rgba8().dimensions(), (1, 1));
+    assert_eq!(image::open(tmp.0.join("cropped/sheet_r0_c1.png")).unwrap().to_rgba8().dimensions(), (4, 4));
+
+    let output = Command::new(EXE).arg("-i").arg(&input).arg("-o").arg(tmp.0.join("uneven.png"))
+        .args(["--split-atlas", "3,2"]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("can't be split into 3 columns and 2 rows"), "{}", stderr);
+}
+
+#[test]
+fn short_palette_from_is_an_error() {
+    let tmp = TempDir::new("short-palette-from");
+    // A 4 color .act: the count follows the 768 byte table, no transparent index.
+    let mut act = vec![0x40; 768];
+    act.extend_from_slice(&[0, 4, 0xff, 0xff]);
+    let palette = tmp.0.join("short.act");
+    fs::write(&palette, act).unwrap();
+
+    let output = tmp.0.join("raditz.png");
+    for strict in [false, true] {
+        let result = Command::new(EXE).args(["-i", RADITZ, "-o"]).arg(&output)
+            .arg("--palette-from").arg(&palette).args(strict.then_some("--strict")).output().unwrap();
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("--palette-from has 4 colors but the image uses index"), "{}", stderr);
+        assert!(!output.exists());
+    }
+
+    // An empty .pal is refused when it's loaded.
+    let empty = tmp.0.join("empty.pal");
+    fs::write(&empty, "JASC-PAL\n0100\n0\n").unwrap();
+    let result = Command::new(EXE).args(["-i", RADITZ, "-o"]).arg(&output).arg("--palette-from").arg(&empty).output().unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("expected 1 to 256 colors, got 0"), "{}", stderr);
+}
+
+#[test]
+fn strip_alpha_applies_to_the_final_palette() {
+    let tmp = TempDir::new("strip-alpha-last");
+    let output = tmp.0.join("raditz.png");
+    let status = Command::new(EXE).args(["-q", "-i", RADITZ, "-o"]).arg(&output)
+        .args(["--strip-alpha", "--alpha-offset=-40", "--alpha-scale", "0.5"]).status().unwrap();
+    assert!(status.success());
+    assert!(image::open(&output).unwrap().to_rgba8().pixels().all(|p| p.0[3] == 255));
+
+    // The --oob-color padding is stripped too.
+    let mut fixture = Fixture::new(4, 4);
+    fixture.clut = (8, 2);
+    fixture.palette.truncate(16);
+    fixture.image = vec![1; 16];
+    fixture.image[5] = 200;
+    let input = tmp.0.join("stray.dbl");
+    fs::write(&input, fixture.build()).unwrap();
+    let output = tmp.0.join("stray.png");
+    let status = Command::new(EXE).arg("-q").arg("-i").arg(&input).arg("-o").arg(&output)
+        .args(["--strip-alpha", "--oob-color", "#04050680"]).status().unwrap();
+    assert!(status.success());
+    assert_eq!(image::open(&output).unwrap().to_rgba8().get_pixel(1, 1).0, [4, 5, 6, 255]);
+}
+
+#[test]
+fn palette_size_must_fit_a_palette() {
+    for size in ["0", "257", "4000000000"] {
+        let output = Command::new(EXE).args(["-i", RADITZ, "--dry-run", "--palette-size", size]).output().unwrap();
+        assert!(!output.status.success(), "{}", size);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("must be between 1 and 256"), "{}: {}", size, stderr);
+    }
+    let status = Command::new(EXE).args(["-q", "-i", RADITZ, "--dry-run", "--palette-size", "256"]).status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn bpp_must_be_4_or_8() {
+    let output = Command::new(EXE).args(["-i", RADITZ, "--dry-run", "--bpp", "16"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--bpp must be 4 or 8"));
+}
+
+#[test]
+fn output_extension_picks_the_format() {
+    let tmp = TempDir::new("extension");
+
+    let output = Command::new(EXE).args(["-i", RADITZ, "-o"]).arg(tmp.0.join("raditz.webp")).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unsupported format 'webp'"), "{}", stderr);
+    assert!(!tmp.0.join("raditz.webp").exists());
+
+    for (name, format) in [("raditz.bmp", image::ImageFormat::Bmp), ("raditz", image::ImageFormat::Png)] {
+        let status = Command::new(EXE).args(["-q", "-i", RADITZ, "-o"]).arg(tmp.0.join(name)).status().unwrap();
+        assert!(status.success());
+        assert_eq!(image::guess_format(&fs::read(tmp.0.join(name)).unwrap()).unwrap(), format);
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::Cursor;
+
+use dbz_sagas_extractor::{deswizzle_palette, AlphaMode, DecodeOptions, SagasColor, SagasColorLUT, ToWriter};
+
+fn decode_alpha(a: u8, alpha_mode: AlphaMode) -> u8 {
+    let options = DecodeOptions { alpha_mode, ..Default::default() };
+    SagasColor::from_reader_with(&mut Cursor::new([10, 20, 30, a]), &options).unwrap().a
+}
+
+#[test]
+fn alpha_modes() {
+    let cases = [
+        (AlphaMode::Double, [0, 1, 255]),
+        (AlphaMode::Raw, [0, 1, 128]),
+        (AlphaMode::Opaque, [255, 255, 255]),
+    ];
+    for (mode, expected) in cases {
+        for (a, want) in [0, 1, 128].into_iter().zip(expected) {
+            assert_eq!(decode_alpha(a, mode), want, "{:?} alpha {}", mode, a);
+        }
+    }
+}
+
+#[test]
+fn alpha_max_scales_double_alpha() {
+    let decode = |a: u8, alpha_max: u8| {
+        let options = DecodeOptions { alpha_max, ..Default::default() };
+        SagasColor::from_reader_with(&mut Cursor::new([10, 20, 30, a]), &options).unwrap().a
+    };
+    // 128 is what the old `(a << 1) - 1` doubling assumed.
+    for a in 1..=128u8 {
+        assert_eq!(decode(a, 128), (((a as u16) << 1) - 1) as u8, "alpha {}", a);
+        assert_eq!(decode(a, 128), decode_alpha(a, AlphaMode::Double));
+    }
+    assert_eq!(decode(0, 128), 0);
+    assert_eq!(decode(200, 128), 255);
+
+    assert_eq!([0, 1, 64, 126, 127, 128].map(|a| decode(a, 127)), [0, 2, 128, 252, 255, 255]);
+}
+
+#[test]
+fn deswizzle_twice_is_identity() {
+    let palette: Vec<u8> = (0..=255u8).flat_map(|i| [i, 0, 0, 0x80]).collect();
+    let options = DecodeOptions { deswizzle: false, ..Default::default() };
+    let mut lut = SagasColorLUT::from_reader_with(&mut Cursor::new(palette), &options).unwrap();
+    let original: Vec<u8> = lut.colors.iter().map(|c| c.r).collect();
+
+    lut.deswizzle();
+    assert_eq!(lut.colors[8].r, 16);
+    assert_eq!(lut.colors[16].r, 8);
+    lut.deswizzle();
+    assert_eq!(lut.colors.iter().map(|c| c.r).collect::<Vec<_>>(), original);
+}
+
+#[test]
+fn color_keys() {
+    let palette: Vec<u8> = (0..16u8).flat_map(|i| [i % 4, 0, 0, 0x80]).collect();
+    let options = DecodeOptions { palette_size: Some(16), ..Default::default() };
+    let mut lut = SagasColorLUT::from_reader_with(&mut Cursor::new(palette), &options).unwrap();
+
+    lut.set_transparent_index(5).unwrap();
+    assert_eq!(lut.colors[5].a, 0);
+    assert!(lut.set_transparent_index(16).is_err());
+
+    assert_eq!(lut.set_transparent_color([2, 0, 0]), 4);
+    assert!(lut.colors.iter().filter(|c| c.r == 2).all(|c| c.a == 0));
+    assert!(lut.colors.iter().filter(|c| c.r == 3).all(|c| c.a == 255));
+}
+
+#[test]
+fn rgba_round_trip() {
+    for a in 0..=255u8 {
+        let c = SagasColor::from_rgba([10, 20, 30, a]);
+        let [r, g, b, decoded] = c.to_rgba();
+        assert_eq!([r, g, b], [10, 20, 30]);
+        // Odd values (and 0) survive exactly, even ones lose their lowest bit.
+        let expected = if a == 0 || a % 2 == 1 { a } else { a - 1 };
+        assert_eq!(decoded, expected, "alpha {}", a);
+        // Once quantized, converting again doesn't change anything.
+        assert_eq!(SagasColor::from_rgba(c.to_rgba()).to_rgba(), c.to_rgba());
+
+        // And it matches what writing and reading the color does.
+        let mut stored = Cursor::new(Vec::new());
+        c.to_writer(&mut stored).unwrap();
+        assert_eq!(decode_alpha(stored.into_inner()[3], AlphaMode::Double), decoded);
+    }
+}
+
+#[test]
+fn deswizzle_palette_swaps_the_middle_quarters() {
+    let colors = |n: u8| (0..n).map(|r| SagasColor { r, g: 0, b: 0, a: 255 }).collect::<Vec<_>>();
+    let order = |colors: &[SagasColor]| colors.iter().map(|c| c.r).collect::<Vec<_>>();
+
+    // Two full blocks of 32 and a partial one that stays put.
+    let mut palette = colors(70);
+    deswizzle_palette(&mut palette, 32);
+    let mut expected: Vec<u8> = Vec::new();
+    for base in [0, 32] {
+        expected.extend(base..base + 8);
+        expected.extend(base + 16..base + 24);
+        expected.extend(base + 8..base + 16);
+        expected.extend(base + 24..base + 32);
+    }
+    expected.extend(64..70);
+    assert_eq!(order(&palette), expected);
+
+    let mut small = colors(16);
+    deswizzle_palette(&mut small, 16);
+    assert_eq!(order(&small), [0, 1, 2, 3, 8, 9, 10, 11, 4, 5, 6, 7, 12, 13, 14, 15]);
+}
+
+#[test]
+fn sorting_by_luminance() {
+    let color = |r, g, b, a| SagasColor { r, g, b, a };
+    let lut = SagasColorLUT {
+        colors: vec![color(255, 255, 255, 255), color(0, 0, 255, 255), color(0, 255, 0, 255),
+                     color(0, 0, 0, 255), color(0, 0, 0, 0)],
+    };
+    let sorted: Vec<_> = lut.sorted_by_luminance().colors.iter().map(|c| c.to_rgba()).collect();
+    assert_eq!(sorted, [[0, 0, 0, 0], [0, 0, 0, 255], [0, 0, 255, 255], [0, 255, 0, 255], [255, 255, 255, 255]]);
+    // The original order is untouched.
+    assert_eq!(lut.colors[0].to_rgba(), [255, 255, 255, 255]);
+}
+
+#[test]
+fn remapping_alpha_clamps() {
+    let color = |a| SagasColor { r: 1, g: 2, b: 3, a };
+    let mut lut = SagasColorLUT { colors: vec![color(0), color(100), color(200), color(255)] };
+    lut.remap_alpha(1.5, -20);
+    let alphas: Vec<u8> = lut.colors.iter().map(|c| c.a).collect();
+    assert_eq!(alphas, [0, 130, 255, 255]);
+
+    lut.remap_alpha(1.0, 0);
+    assert_eq!(lut.colors[1].to_rgba(), [1, 2, 3, 130]);
+}
+
+#[test]
+fn linear_rgba_follows_srgb() {
+    let [r, g, b, a] = SagasColor { r: 0, g: 128, b: 255, a: 128 }.to_linear_rgba();
+    assert_eq!((r, b), (0.0, 1.0));
+    assert!((g - 0.2158).abs() < 1e-4, "{}", g);
+    // Alpha is only scaled.
+    assert!((a - 128.0 / 255.0).abs() < 1e-6);
+    let [dark, ..] = SagasColor { r: 10, g: 0, b: 0, a: 255 }.to_linear_rgba();
+    assert!((dark - 10.0 / 255.0 / 12.92).abs() < 1e-6);
+}
+
+#[test]
+fn hex_round_trip() {
+    let color = SagasColor { r: 0x12, g: 0xab, b: 0x00, a: 0x7f };
+    assert_eq!(color.to_string(), "#12ab007f");
+    assert_eq!(color.to_string().parse::<SagasColor>().unwrap().to_rgba(), color.to_rgba());
+
+    assert_eq!("#FF8000".parse::<SagasColor>().unwrap().to_rgba(), [0xff, 0x80, 0x00, 0xff]);
+    assert_eq!("ff800040".parse::<SagasColor>().unwrap().to_rgba(), [0xff, 0x80, 0x00, 0x40]);
+    for bad in ["", "#", "#fff", "#ff8000f", "#ff80zz", "#+f8000", "#ff800040ff"] {
+        assert!(bad.parse::<SagasColor>().is_err(), "{}", bad);
+    }
+}
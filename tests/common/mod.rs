@@ -0,0 +1,64 @@
+#![allow(dead_code)]
+
+// Builds synthetic Sagas files with the same layout as the retail portraits.
+pub struct Fixture {
+    pub width: u16,
+    pub height: u16,
+    pub clut: (u16, u16),     // unk12 x unk13
+    pub palette: Vec<[u8; 4]>, // stored (not yet doubled) RGBA
+    pub image: Vec<u8>,        // raw image bytes as stored
+    pub texture: &'static str, // string1
+    pub big_endian: bool,
+}
+
+impl Fixture {
+    // A width x height image using a full 256 color palette.
+    pub fn new(width: u16, height: u16) -> Self {
+        Fixture {
+            width,
+            height,
+            clut: (16, 16),
+            palette: (0..=255u8).map(|i| [i, 255 - i, i / 2, 0x80]).collect(),
+            image: (0..width as usize * height as usize).map(|i| i as u8).collect(),
+            texture: "Bit08_000.tga",
+            big_endian: false,
+        }
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let color_table_offset = 0xa0u32;
+        let image_offset = color_table_offset + 4 * self.palette.len() as u32;
+
+        let be = self.big_endian;
+        let u16_bytes = |v: u16| if be { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32_bytes = |v: u32| if be { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&if be { 1u64.to_be_bytes() } else { 1u64.to_le_bytes() });
+        for v in [0x90u32, 1, 1, 128] {
+            data.extend_from_slice(&u32_bytes(v));
+        }
+        data.extend_from_slice(b"c:\\dev\\dbz\\data_ps2\\sprites\\test\0");
+        for v in [0x409u32, 0, 0, image_offset] {
+            data.extend_from_slice(&u32_bytes(v));
+        }
+        data.extend_from_slice(&u16_bytes(self.width));
+        data.extend_from_slice(&u16_bytes(self.height));
+        for v in [0u32, 0, color_table_offset] {
+            data.extend_from_slice(&u32_bytes(v));
+        }
+        data.extend_from_slice(&u16_bytes(self.clut.0));
+        data.extend_from_slice(&u16_bytes(self.clut.1));
+        data.extend_from_slice(&u32_bytes(64));
+        data.extend_from_slice(self.texture.as_bytes());
+        data.push(0);
+
+        data.resize(color_table_offset as usize, 0);
+        for c in &self.palette {
+            data.extend_from_slice(c);
+        }
+        data.resize(image_offset as usize, 0);
+        data.extend_from_slice(&self.image);
+        data
+    }
+}
@@ -0,0 +1,55 @@
+# Regenerates the golden fixtures. Run from the repository root:
+#   python3 tests/fixtures/generate.py
+import struct, zlib
+
+raditz = open('data/raditz.dbl', 'rb').read()
+
+def dbl(width, height, palette, image):
+    d = bytearray(raditz[:0xa0])
+    d[0x48:0x4c] = struct.pack('<HH', width, height)
+    d[0x84:0x88] = struct.pack('<HH', width, height)
+    for c in palette:
+        d += bytes(c)
+    d += bytes(image)
+    return bytes(d)
+
+def swz(i):
+    m = i % 32
+    if 8 <= m < 16: return i + 8
+    if 16 <= m < 24: return i - 8
+    return i
+
+def double(a):
+    return 0 if a == 0 else 2 * a - 1
+
+def png(width, height, rgba):
+    def chunk(t, data):
+        c = t + data
+        return struct.pack('>I', len(data)) + c + struct.pack('>I', zlib.crc32(c) & 0xffffffff)
+    raw = b''.join(b'\0' + bytes(rgba[y * width * 4:(y + 1) * width * 4]) for y in range(height))
+    return (b'\x89PNG\r\n\x1a\n' + chunk(b'IHDR', struct.pack('>IIBBBBB', width, height, 8, 6, 0, 0, 0))
+            + chunk(b'IDAT', zlib.compress(raw, 9)) + chunk(b'IEND', b''))
+
+def write(name, width, height, palette, image):
+    open(f'tests/fixtures/{name}.dbl', 'wb').write(dbl(width, height, palette, image))
+    rgba = []
+    for i in image:
+        r, g, b, a = palette[swz(i)]
+        rgba += [r, g, b, double(a)]
+    open(f'tests/fixtures/{name}.png', 'wb').write(png(width, height, rgba))
+
+# Alpha ramps from 0 to 0x80 across the palette, every index used once.
+alpha_palette = [(i, 255 - i, (i * 3) & 0xff, (i * 128 + 127) // 255) for i in range(256)]
+write('alpha_ramp', 16, 16, alpha_palette, [(y * 16 + x) for y in range(16) for x in range(16)])
+
+# Opaque, every entry a distinct color, index pattern crosses swizzle blocks.
+swizzle_palette = [((i * 37) & 0xff, (i * 91 + 13) & 0xff, i ^ 0x5a, 0x80) for i in range(256)]
+write('swizzle_blocks', 32, 8, swizzle_palette, [(x * 8 + y) & 0xff for y in range(8) for x in range(32)])
+
+# The real portrait, decoded independently of the crate.
+pal = [tuple(raditz[0xa0 + 4 * i:0xa4 + 4 * i]) for i in range(256)]
+rgba = []
+for i in raditz[0x4a0:0x4a0 + 128 * 128]:
+    r, g, b, a = pal[swz(i)]
+    rgba += [r, g, b, double(a)]
+open('tests/fixtures/raditz.png', 'wb').write(png(128, 128, rgba))
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use dbz_sagas_extractor::parse_file;
+use image::RgbaImage;
+
+// Decodes `dbl` and compares it pixel for pixel with `golden`. The golden
+// PNGs come from tests/fixtures/generate.py, not from this crate.
+fn assert_matches_golden(dbl: &str, golden: &str) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let sf = parse_file(root.join(dbl)).unwrap();
+    let rendered = RgbaImage::try_from(&sf).unwrap();
+    let expected = image::open(root.join(golden)).unwrap().to_rgba8();
+
+    assert_eq!(rendered.dimensions(), expected.dimensions(), "{}", dbl);
+    for ((x, y, got), want) in rendered.enumerate_pixels().zip(expected.pixels()) {
+        assert_eq!(got, want, "{} differs at {},{}", dbl, x, y);
+    }
+}
+
+#[test]
+fn raditz() {
+    assert_matches_golden("data/raditz.dbl", "tests/fixtures/raditz.png");
+}
+
+#[test]
+fn alpha_ramp() {
+    // Stored alpha runs 0..=0x80 and has to come out as 0..=0xff.
+    assert_matches_golden("tests/fixtures/alpha_ramp.dbl", "tests/fixtures/alpha_ramp.png");
+}
+
+#[test]
+fn swizzle_blocks() {
+    // Every palette entry is distinct, so any deswizzle mistake shows up.
+    assert_matches_golden("tests/fixtures/swizzle_blocks.dbl", "tests/fixtures/swizzle_blocks.png");
+}
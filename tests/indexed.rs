@@ -0,0 +1,122 @@
+mod common;
+
+use std::io::Cursor;
+
+use dbz_sagas_extractor::{flatten, index_map, write_animated_gif, FromReader, IndexImage, IndexPacking, SagasColorLUT, SagasError, SagasFile};
+use image::RgbaImage;
+
+const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+
+#[test]
+fn indexed_png_matches_rgba_render() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let mut png = Vec::new();
+    sf.write_indexed_png(&mut png).unwrap();
+
+    let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+    let colors = &sf.get_color_table().colors;
+    for (pixel, &i) in decoded.pixels().zip(sf.get_image()) {
+        let c = colors[i as usize];
+        assert_eq!(pixel.0, [c.r, c.g, c.b, c.a]);
+    }
+}
+
+#[test]
+fn gif_uses_the_palette() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let mut gif = Vec::new();
+    sf.get_color_table().write_indexed_gif(&sf.index_image(), &mut gif).unwrap();
+
+    let decoded = image::load_from_memory(&gif).unwrap().to_rgba8();
+    assert_eq!(decoded.dimensions(), (128, 128));
+    let colors = &sf.get_color_table().colors;
+    for (pixel, &i) in decoded.pixels().zip(sf.get_image()) {
+        let c = colors[i as usize];
+        if c.a == 255 {
+            assert_eq!(pixel.0, [c.r, c.g, c.b, 255]);
+        }
+    }
+}
+
+#[test]
+fn rgba_conversion_looks_up_the_palette() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let image = RgbaImage::try_from(&sf).unwrap();
+    assert_eq!(image.dimensions(), (128, 128));
+    let colors = &sf.get_color_table().colors;
+    for (pixel, &i) in image.pixels().zip(sf.get_image()) {
+        let c = colors[i as usize];
+        assert_eq!(pixel.0, [c.r, c.g, c.b, c.a]);
+    }
+}
+
+#[test]
+fn rgba_conversion_rejects_indices_outside_the_palette() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let mut lut = SagasColorLUT { colors: sf.get_color_table().colors[..16].to_vec() };
+    assert!(matches!(lut.render(&sf.index_image()), Err(SagasError::IndexOutOfRange { palette_size: 16, .. })));
+    lut.colors.clear();
+    assert!(lut.render(&IndexImage::new(0, 0)).unwrap().is_empty());
+}
+
+#[test]
+fn flatten_blends_over_the_background() {
+    let image = RgbaImage::from_raw(3, 1, vec![
+        10, 20, 30, 255,
+        10, 20, 30, 0,
+        200, 100, 0, 128,
+    ]).unwrap();
+    let flat = flatten(&image, [255, 0, 255]);
+    assert_eq!(flat.as_raw(), &[10, 20, 30, 255, 0, 255, 227, 50, 127]);
+}
+
+#[test]
+fn animated_gif_has_every_frame() {
+    use image::AnimationDecoder;
+
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = sf.get_color_table();
+    let mut flipped = sf.index_image();
+    image::imageops::flip_horizontal_in_place(&mut flipped);
+    let frames = [(lut, sf.index_image()), (lut, flipped)];
+
+    let mut gif = Vec::new();
+    write_animated_gif(&frames, 25, &mut gif).unwrap();
+    let decoded = image::codecs::gif::GifDecoder::new(Cursor::new(gif)).unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap();
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded[1].delay().numer_denom_ms(), (250, 1));
+    assert_eq!(decoded[0].buffer().dimensions(), (128, 128));
+
+    let mismatched = [(lut, sf.index_image()), (lut, IndexImage::new(4, 4))];
+    assert!(matches!(write_animated_gif(&mismatched, 10, &mut Vec::new()),
+                     Err(SagasError::FrameSizeMismatch { frame: 1, expected: (128, 128), found: (4, 4) })));
+}
+
+#[test]
+fn visible_bounds_skips_transparent_borders() {
+    let mut fixture = common::Fixture::new(8, 8);
+    fixture.clut = (8, 2);
+    fixture.palette = vec![[0xff, 0xff, 0xff, 0x80]; 16];
+    fixture.palette[0][3] = 0;
+    fixture.image = vec![0; 64];
+    let sf = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+    assert_eq!(sf.get_color_table().visible_bounds(&sf.index_image()), None);
+
+    fixture.image[2 * 8 + 3] = 1;
+    fixture.image[4 * 8 + 4] = 1;
+    let sf = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+    assert_eq!(sf.get_color_table().visible_bounds(&sf.index_image()), Some((3, 2, 2, 3)));
+}
+
+#[test]
+fn index_maps_pack_the_indices() {
+    let indices = IndexImage::from_raw(3, 1, vec![0, 7, 255]).unwrap();
+    let red = index_map(&indices, IndexPacking::Red);
+    assert_eq!(red.pixels().map(|p| p.0).collect::<Vec<_>>(), [[0, 0, 0, 255], [7, 0, 0, 255], [255, 0, 0, 255]]);
+    let gray = index_map(&indices, "gray".parse().unwrap());
+    assert_eq!(gray.get_pixel(1, 0).0, [7, 7, 7, 255]);
+    assert!("blue".parse::<IndexPacking>().is_err());
+}
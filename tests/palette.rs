@@ -0,0 +1,76 @@
+use std::io::Cursor;
+
+use dbz_sagas_extractor::{FromReader, PaletteFormat, SagasColorLUT, SagasError, SagasFile};
+
+const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+
+#[test]
+fn exports_palette_formats() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = sf.get_color_table();
+
+    let mut act = Vec::new();
+    lut.export(PaletteFormat::Act, "raditz", &mut act).unwrap();
+    assert_eq!(act.len(), 768);
+    let c = lut.colors[1];
+    assert_eq!(&act[3..6], &[c.r, c.g, c.b]);
+
+    let mut pal = Vec::new();
+    lut.export(PaletteFormat::Pal, "raditz", &mut pal).unwrap();
+    let pal = String::from_utf8(pal).unwrap();
+    assert!(pal.starts_with("JASC-PAL\r\n0100\r\n256\r\n"));
+    assert_eq!(pal.lines().count(), 3 + 256);
+
+    let mut gpl = Vec::new();
+    lut.export(PaletteFormat::Gpl, "raditz", &mut gpl).unwrap();
+    let gpl = String::from_utf8(gpl).unwrap();
+    assert!(gpl.starts_with("GIMP Palette\nName: raditz\n"));
+}
+
+#[test]
+fn swatch_image_is_a_16x16_grid() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = sf.get_color_table();
+    let swatches = lut.swatch_image(16);
+    assert_eq!(swatches.dimensions(), (256, 256));
+
+    // Entry 0 is fully transparent, so it shows the checkerboard.
+    assert_eq!(lut.colors[0].a, 0);
+    assert_ne!(swatches.get_pixel(0, 0), swatches.get_pixel(8, 0));
+}
+
+#[test]
+fn imports_exported_palettes() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = sf.get_color_table();
+    let rgb = |lut: &SagasColorLUT| lut.colors.iter().map(|c| [c.r, c.g, c.b]).collect::<Vec<_>>();
+
+    for format in [PaletteFormat::Act, PaletteFormat::Pal] {
+        let mut data = Vec::new();
+        lut.export(format, "raditz", &mut data).unwrap();
+        let imported = SagasColorLUT::import(format, &mut Cursor::new(data)).unwrap();
+        assert_eq!(rgb(&imported), rgb(lut));
+        assert!(imported.colors.iter().all(|c| c.a == 255));
+    }
+}
+
+#[test]
+fn act_trailer_sets_count_and_transparency() {
+    let mut act = vec![0u8; 768];
+    act[3..6].copy_from_slice(&[1, 2, 3]);
+    act.extend_from_slice(&[0, 16, 0, 1]);
+    let lut = SagasColorLUT::import(PaletteFormat::Act, &mut Cursor::new(act)).unwrap();
+    assert_eq!(lut.colors.len(), 16);
+    assert_eq!([lut.colors[1].r, lut.colors[1].g, lut.colors[1].b, lut.colors[1].a], [1, 2, 3, 0]);
+}
+
+#[test]
+fn malformed_palettes_are_rejected() {
+    let bad = |format, data: &[u8]| {
+        matches!(SagasColorLUT::import(format, &mut Cursor::new(data)), Err(SagasError::BadPalette(_)))
+    };
+    assert!(bad(PaletteFormat::Act, &[0; 100]));
+    assert!(bad(PaletteFormat::Pal, b"GIMP Palette\n"));
+    assert!(bad(PaletteFormat::Pal, b"JASC-PAL\r\n0100\r\n2\r\n1 2 3\r\n"));
+    assert!(bad(PaletteFormat::Pal, b"JASC-PAL\r\n0100\r\n1\r\n1 2 300\r\n"));
+}
@@ -0,0 +1,356 @@
+mod common;
+
+use std::ffi::CString;
+use std::io::Cursor;
+
+use common::Fixture;
+use dbz_sagas_extractor::{parse_bytes, parse_file, DecodeOptions, Endian, FromReader, SagasError, SagasFile, SagasHeader, ToWriter};
+
+const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+
+#[test]
+fn parses_raditz() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let header = sf.get_header();
+    assert_eq!((header.width, header.height), (128, 128));
+    assert_eq!(sf.get_color_table().colors.len(), 256);
+    assert_eq!(sf.get_image().len(), 128 * 128);
+    assert_eq!(header.source_path(), "c:\\dev\\dbz\\data_ps2\\sprites\\fac");
+    assert_eq!(header.secondary_string(), "Bit08_000.tga");
+}
+
+#[test]
+fn truncated_palette_is_an_error() {
+    // The palette starts at 0xa0 and is 1024 bytes long, cut it off halfway.
+    let data = &RADITZ[..0xa0 + 512];
+    match SagasFile::from_reader(&mut Cursor::new(data)) {
+        Err(SagasError::Truncated) => {},
+        other => panic!("expected a truncation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn short_image_data_is_bad_dimensions() {
+    let data = &RADITZ[..RADITZ.len() - 1];
+    assert!(matches!(SagasFile::from_reader(&mut Cursor::new(data)), Err(SagasError::BadDimensions)));
+}
+
+#[test]
+fn zero_width_is_bad_dimensions() {
+    let mut data = RADITZ.to_vec();
+    data[0x48..0x4a].copy_from_slice(&[0, 0]);
+    assert!(matches!(SagasFile::from_reader(&mut Cursor::new(data)), Err(SagasError::BadDimensions)));
+}
+
+#[test]
+fn probe_checks_signature_without_consuming() {
+    let mut cursor = Cursor::new(RADITZ);
+    assert!(SagasFile::probe(&mut cursor));
+    assert_eq!(cursor.position(), 0);
+
+    let mut junk = Cursor::new(b"PNG\x89 definitely not a portrait".to_vec());
+    assert!(!SagasFile::probe(&mut junk));
+    assert_eq!(junk.position(), 0);
+
+    assert!(!SagasFile::probe(&mut Cursor::new(&RADITZ[..4])));
+}
+
+#[test]
+fn sixteen_color_palette() {
+    let mut fixture = Fixture::new(8, 8);
+    fixture.clut = (8, 2);
+    fixture.palette.truncate(16);
+    fixture.image = (0..64).map(|i| i % 16).collect();
+
+    let sf = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+    let colors = &sf.get_color_table().colors;
+    assert_eq!(colors.len(), 16);
+    // Too small to be swizzled.
+    assert_eq!(colors.iter().map(|c| c.r).collect::<Vec<_>>(), (0..16).collect::<Vec<_>>());
+    assert!(sf.check_indices().is_ok());
+
+    fixture.image[10] = 200;
+    let sf = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+    assert!(matches!(sf.check_indices(), Err(SagasError::IndexOutOfRange { index: 200, palette_size: 16 })));
+}
+
+#[test]
+fn interior_nul_is_a_bad_string() {
+    // The conversion `CString::from_reader` relies on.
+    let e = SagasError::from(CString::new(b"c:\\dev\0fac".to_vec()).unwrap_err());
+    assert!(matches!(e, SagasError::BadString(_)));
+
+    // Strings are only delimited by their NUL, so in a header the first one
+    // ends the string: it never holds an interior NUL, and what followed it
+    // is read as the next fields.
+    let mut data = Fixture::new(4, 4).build();
+    let at = data.windows(12).position(|w| w == b"sprites\\test").unwrap();
+    data[at + 7] = 0;
+    let header = SagasHeader::from_reader(&mut Cursor::new(&data)).unwrap();
+    assert_eq!(header.string0.as_bytes(), b"c:\\dev\\dbz\\data_ps2\\sprites");
+    assert_eq!(header.unk5, u32::from_le_bytes(*b"test"));
+}
+
+#[test]
+fn big_endian_header() {
+    let mut fixture = Fixture::new(8, 4);
+    fixture.clut = (8, 2);
+    fixture.palette.truncate(16);
+    fixture.image = (0..32).map(|i| i % 16).collect();
+    let little = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+
+    fixture.big_endian = true;
+    let data = fixture.build();
+    let mut cursor = Cursor::new(data);
+    assert!(SagasFile::probe(&mut cursor));
+    let options = DecodeOptions { endian: Endian::Big, ..DecodeOptions::default() };
+    let big = SagasFile::from_reader_with(&mut cursor, &options).unwrap();
+
+    let (l, b) = (little.get_header(), big.get_header());
+    assert_eq!((b.width, b.height), (8, 4));
+    assert_eq!((b.unk0, b.unk1, b.unk5), (l.unk0, l.unk1, l.unk5));
+    assert_eq!((b.image_offset, b.color_table_offset), (l.image_offset, l.color_table_offset));
+    assert_eq!((b.unk12, b.unk13, b.unk14), (l.unk12, l.unk13, l.unk14));
+    assert_eq!(b.string1, l.string1);
+    assert_eq!(big.get_image(), little.get_image());
+    assert_eq!(big.get_color_table().colors.len(), 16);
+}
+
+#[test]
+fn hashes_are_stable() {
+    // Changing this value breaks manifests people already have.
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    assert_eq!(sf.index_hash(), 0x5945_7ced_84fc_726c);
+    assert_eq!(sf.content_hash(), 0x3863_f7c5_3874_c41e);
+}
+
+#[test]
+fn content_hash_ignores_unknown_fields() {
+    let data = Fixture::new(16, 8).build();
+    let mut other = data.clone();
+    other[0..8].copy_from_slice(&7u64.to_le_bytes());
+
+    let a = SagasFile::from_reader(&mut Cursor::new(data)).unwrap();
+    let b = SagasFile::from_reader(&mut Cursor::new(other)).unwrap();
+    assert_ne!(a.get_header().unk0, b.get_header().unk0);
+    assert_eq!(a.content_hash(), b.content_hash());
+
+    let mut fixture = Fixture::new(16, 8);
+    fixture.image[3] = 99;
+    let c = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+    assert_ne!(a.content_hash(), c.content_hash());
+}
+
+#[test]
+fn offsets_past_the_end_are_out_of_range() {
+    let len = RADITZ.len() as u64;
+
+    let mut image_past_end = RADITZ.to_vec();
+    image_past_end[0x44..0x48].copy_from_slice(&0x10000u32.to_le_bytes());
+    match SagasFile::from_reader(&mut Cursor::new(image_past_end)) {
+        Err(SagasError::OffsetOutOfRange { offset: 0x10000, len: l }) => assert_eq!(l, len),
+        other => panic!("expected an out of range offset, got {:?}", other),
+    }
+
+    let mut palette_past_end = RADITZ.to_vec();
+    palette_past_end[0x54..0x58].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(SagasFile::from_reader(&mut Cursor::new(palette_past_end)),
+                     Err(SagasError::OffsetOutOfRange { offset: 0xffff_ffff, .. })));
+}
+
+#[test]
+fn convenience_parsers() {
+    let from_bytes = parse_bytes(RADITZ).unwrap();
+    let from_file = parse_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/raditz.dbl")).unwrap();
+    assert_eq!(from_bytes.get_image(), from_file.get_image());
+    assert!(matches!(parse_bytes(&RADITZ[..16]), Err(SagasError::Truncated)));
+    let try_from = SagasFile::try_from(RADITZ).unwrap();
+    assert_eq!(try_from.get_image(), from_bytes.get_image());
+    assert!(matches!(SagasFile::try_from(&RADITZ[..16]), Err(SagasError::Truncated)));
+    assert!(matches!(parse_file("data/does-not-exist.dbl"), Err(SagasError::Io(_))));
+}
+
+#[test]
+fn header_fields_match_the_stored_bytes() {
+    for big_endian in [false, true] {
+        let mut fixture = Fixture::new(16, 8);
+        fixture.big_endian = big_endian;
+        let data = fixture.build();
+        let endian = if big_endian { Endian::Big } else { Endian::Little };
+        let options = DecodeOptions { endian, ..DecodeOptions::default() };
+        let sf = SagasFile::from_reader_with(&mut Cursor::new(&data), &options).unwrap();
+
+        let fields = sf.get_header().fields(endian);
+        assert_eq!(fields.len(), 19);
+        for field in &fields {
+            assert_eq!(&data[field.offset..field.offset + field.bytes.len()], &field.bytes[..], "{}", field.name);
+        }
+        let width = fields.iter().find(|f| f.name == "width").unwrap();
+        assert_eq!((width.offset, width.value.as_str()), (0x49, "16"));
+        let last = fields.last().unwrap();
+        assert_eq!(last.offset + last.bytes.len(), sf.get_header().size());
+    }
+}
+
+#[test]
+fn odd_dimensions_are_warned_about() {
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    assert!(raditz.get_header().dimension_warnings().is_empty());
+
+    let sf = SagasFile::from_reader(&mut Cursor::new(Fixture::new(160, 8).build())).unwrap();
+    let warnings = sf.get_header().dimension_warnings();
+    assert_eq!(warnings, ["width 160 is not a power of two"]);
+
+    let mut header = sf.get_header().clone();
+    header.height = 4096;
+    assert!(header.dimension_warnings()[1].contains("misaligned"));
+}
+
+#[test]
+fn padded_rows_are_read_with_a_stride() {
+    // 6x4 image stored in 8 byte rows, the padding is 0xee.
+    let mut fixture = Fixture::new(6, 4);
+    fixture.image = (0..32).map(|i| if i % 8 < 6 { (i / 8 * 6 + i % 8) as u8 } else { 0xee }).collect();
+    let data = fixture.build();
+
+    let options = DecodeOptions { stride: Some(8), ..DecodeOptions::default() };
+    let sf = SagasFile::from_reader_with(&mut Cursor::new(&data), &options).unwrap();
+    assert_eq!(sf.get_image(), (0..24).collect::<Vec<u8>>());
+    assert_eq!(sf.record_len().unwrap(), data.len() as u64);
+
+    let mut written = Cursor::new(Vec::new());
+    sf.to_writer(&mut written).unwrap();
+    let reread = SagasFile::from_reader_with(&mut Cursor::new(written.into_inner()), &options).unwrap();
+    assert_eq!(reread.get_image(), sf.get_image());
+
+    let too_small = DecodeOptions { stride: Some(5), ..DecodeOptions::default() };
+    assert!(matches!(SagasFile::from_reader_with(&mut Cursor::new(&data), &too_small),
+                     Err(SagasError::StrideTooSmall { stride: 5, row_bytes: 6 })));
+    let too_large = DecodeOptions { stride: Some(9), ..DecodeOptions::default() };
+    assert!(matches!(SagasFile::from_reader_with(&mut Cursor::new(&data), &too_large),
+                     Err(SagasError::BadDimensions)));
+    let overflowing = DecodeOptions { stride: Some(usize::MAX), ..DecodeOptions::default() };
+    assert!(matches!(SagasFile::from_reader_with(&mut Cursor::new(&data), &overflowing),
+                     Err(SagasError::BadDimensions)));
+}
+
+#[test]
+fn header_and_palette_without_the_image() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let parts = SagasFile::read_header_and_palette(&mut Cursor::new(RADITZ)).unwrap();
+    assert!(parts.image.is_none());
+    assert_eq!(parts.header.width, sf.get_header().width);
+    let rgba = |lut: &dbz_sagas_extractor::SagasColorLUT| lut.colors.iter().map(|c| c.to_rgba()).collect::<Vec<_>>();
+    assert_eq!(rgba(&parts.lut), rgba(sf.get_color_table()));
+
+    // Only the palette has to be there.
+    let truncated = &RADITZ[..0x4a0];
+    assert!(SagasFile::from_reader(&mut Cursor::new(truncated)).is_err());
+    assert!(SagasFile::read_header_and_palette(&mut Cursor::new(truncated)).is_ok());
+}
+
+#[test]
+fn bit_depth_from_the_header() {
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    assert_eq!(raditz.get_header().bit_depth(), Some(8));
+
+    // (unk12 x unk13, string1, expected)
+    let cases = [
+        ((16, 16), "Bit08_000.tga", Some(8)),
+        ((8, 2), "Bit04_000.tga", Some(4)),
+        ((8, 2), "Bit08_000.tga", Some(8)),
+        ((16, 16), "Bit04_000.tga", Some(4)),
+        ((8, 2), "portrait.tga", Some(4)),
+        ((16, 16), "portrait.tga", Some(8)),
+        ((0, 0), "portrait.tga", None),
+        ((4, 8), "", None),
+    ];
+    for (clut, texture, expected) in cases {
+        let mut fixture = Fixture::new(4, 4);
+        fixture.clut = clut;
+        fixture.texture = texture;
+        let header = SagasHeader::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+        assert_eq!(header.bit_depth(), expected, "{:?} {:?}", clut, texture);
+        assert_eq!(header.bits_per_pixel(), expected.unwrap_or(8));
+    }
+}
+
+#[test]
+fn forced_dimensions_replace_the_header() {
+    let options = |width, height| DecodeOptions { dimensions: Some((width, height)), ..DecodeOptions::default() };
+    let sf = SagasFile::from_reader_with(&mut Cursor::new(RADITZ), &options(64, 256)).unwrap();
+    assert_eq!((sf.get_header().width, sf.get_header().height), (64, 256));
+    assert_eq!(sf.index_image().dimensions(), (64, 256));
+    assert_eq!(sf.get_image(), parse_bytes(RADITZ).unwrap().get_image());
+
+    // More pixels than the file holds.
+    assert!(matches!(SagasFile::from_reader_with(&mut Cursor::new(RADITZ), &options(256, 256)),
+                     Err(SagasError::BadDimensions)));
+}
+
+#[test]
+fn offsets_can_be_overridden() {
+    let options = DecodeOptions { image_offset: Some(0xa0), color_table_offset: Some(0x4a0), ..DecodeOptions::default() };
+    let sf = SagasFile::from_reader_with(&mut Cursor::new(RADITZ), &options).unwrap();
+    assert_eq!(sf.get_image(), &RADITZ[0xa0..0xa0 + 128 * 128]);
+    assert_eq!(sf.get_header().color_table_offset, 0x4a0);
+    let first = sf.get_color_table().colors[0];
+    assert_eq!([first.r, first.g, first.b], RADITZ[0x4a0..0x4a3]);
+
+    let past_end = DecodeOptions { image_offset: Some(0x10_0000), ..DecodeOptions::default() };
+    assert!(matches!(SagasFile::from_reader_with(&mut Cursor::new(RADITZ), &past_end),
+                     Err(SagasError::OffsetOutOfRange { offset: 0x10_0000, .. })));
+}
+
+#[test]
+fn unterminated_strings_are_truncated() {
+    let read = |bytes: &[u8]| CString::from_reader(&mut Cursor::new(bytes.to_vec()));
+    assert_eq!(read(b"fac\0rest").unwrap().as_bytes(), b"fac");
+    assert!(matches!(read(b"fac"), Err(SagasError::Truncated)));
+    assert!(matches!(read(b""), Err(SagasError::Truncated)));
+
+    // Cut off in the middle of string0.
+    assert!(matches!(parse_bytes(&RADITZ[..0x30]), Err(SagasError::Truncated)));
+}
+
+#[test]
+fn palette_right_before_the_image() {
+    let original = parse_bytes(RADITZ).unwrap();
+    let first = |sf: &SagasFile| sf.get_color_table().colors[0].to_rgba();
+
+    // An unset offset finds the table that ends where the image starts.
+    let mut unset = RADITZ.to_vec();
+    unset[0x54..0x58].copy_from_slice(&0u32.to_le_bytes());
+    let sf = parse_bytes(&unset).unwrap();
+    assert_eq!(sf.get_header().color_table_offset, 0xa0);
+    assert_eq!(first(&sf), first(&original));
+
+    // Forced, it also overrides an offset that looks fine.
+    let mut wrong = RADITZ.to_vec();
+    wrong[0x54..0x58].copy_from_slice(&0x1000u32.to_le_bytes());
+    let inline = DecodeOptions { inline_palette: true, ..DecodeOptions::default() };
+    let sf = SagasFile::from_reader_with(&mut Cursor::new(wrong), &inline).unwrap();
+    assert_eq!(sf.get_header().color_table_offset, 0xa0);
+    assert_eq!(first(&sf), first(&original));
+
+    // Not enough bytes between the header and the image for 256 colors.
+    let mut cramped = unset.clone();
+    cramped[0x44..0x48].copy_from_slice(&0x200u32.to_le_bytes());
+    assert!(matches!(SagasFile::from_reader_with(&mut Cursor::new(cramped), &inline),
+                     Err(SagasError::NoRoomForPalette { image_offset: 0x200, palette_bytes: 1024 })));
+}
+
+#[test]
+fn palette_sizes_below_the_swizzle_block() {
+    // 24 entries is less than one 32 entry swizzle block, which stays as stored.
+    let mut fixture = Fixture::new(4, 6);
+    fixture.image = (0..24).collect();
+    let options = DecodeOptions { palette_size: Some(24), ..DecodeOptions::default() };
+    let sf = SagasFile::from_reader_with(&mut Cursor::new(fixture.build()), &options).unwrap();
+    let colors = &sf.get_color_table().colors;
+    assert_eq!(colors.len(), 24);
+    for (i, c) in colors.iter().enumerate() {
+        assert_eq!([c.r, c.g, c.b], fixture.palette[i][..3], "entry {}", i);
+    }
+    sf.check_indices().unwrap();
+}
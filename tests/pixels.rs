@@ -0,0 +1,36 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::Fixture;
+use dbz_sagas_extractor::{pack_4bpp, unpack_4bpp, DecodeOptions, FromReader, SagasError, SagasFile};
+
+#[test]
+fn pack_unpack_round_trip() {
+    let indices: Vec<u8> = (0..33).map(|i| (i * 7 % 16) as u8).collect();
+    let packed = pack_4bpp(&indices);
+    assert_eq!(packed.len(), 17);
+    assert_eq!(packed[0], indices[0] | indices[1] << 4);
+    assert_eq!(unpack_4bpp(&packed, indices.len()), indices);
+}
+
+#[test]
+fn parses_4bpp_image() {
+    let indices: Vec<u8> = (0..64).map(|i| (i % 16) as u8).collect();
+    let mut fixture = Fixture::new(8, 8);
+    fixture.clut = (8, 2);
+    fixture.palette.truncate(16);
+    fixture.image = pack_4bpp(&indices);
+    fixture.texture = "Bit04_000.tga";
+
+    let sf = SagasFile::from_reader(&mut Cursor::new(fixture.build())).unwrap();
+    assert_eq!(sf.bits_per_pixel(), 4);
+    assert_eq!(sf.get_image(), indices.as_slice());
+}
+
+#[test]
+fn unsupported_bit_depth_is_an_error() {
+    let options = DecodeOptions { bits_per_pixel: Some(16), ..DecodeOptions::default() };
+    let result = SagasFile::from_reader_with(&mut Cursor::new(Fixture::new(8, 8).build()), &options);
+    assert!(matches!(result, Err(SagasError::UnsupportedBitDepth(16))));
+}
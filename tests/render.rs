@@ -0,0 +1,171 @@
+mod common;
+
+use dbz_sagas_extractor::{apply_gamma, contact_sheet, decode_to_rgba, edge_bleed, fit_nearest, parse_bytes, to_planar, to_rgba16, write_png16, IndexImage, SagasColor, SagasColorLUT, SagasError};
+use image::{Rgba, RgbaImage};
+
+#[test]
+fn edge_bleed_fills_transparent_neighbours() {
+    // A single visible red pixel in the middle of a transparent 5x1 strip.
+    let mut image = RgbaImage::from_pixel(5, 1, Rgba([0, 0, 0, 0]));
+    image.put_pixel(2, 0, Rgba([200, 10, 10, 255]));
+
+    let mut one = image.clone();
+    edge_bleed(&mut one, 1);
+    let row: Vec<_> = one.pixels().map(|p| p.0).collect();
+    assert_eq!(row, [[0, 0, 0, 0], [200, 10, 10, 0], [200, 10, 10, 255], [200, 10, 10, 0], [0, 0, 0, 0]]);
+
+    let mut all = image.clone();
+    edge_bleed(&mut all, 10);
+    assert!(all.pixels().all(|p| p.0[..3] == [200, 10, 10]));
+    assert!(all.pixels().enumerate().all(|(i, p)| (p.0[3] == 255) == (i == 2)));
+
+    let mut none = image.clone();
+    edge_bleed(&mut none, 0);
+    assert_eq!(none, image);
+}
+
+#[test]
+fn edge_bleed_averages_neighbours() {
+    let mut image = RgbaImage::from_pixel(3, 1, Rgba([0, 0, 0, 0]));
+    image.put_pixel(0, 0, Rgba([100, 0, 0, 255]));
+    image.put_pixel(2, 0, Rgba([0, 0, 200, 128]));
+    edge_bleed(&mut image, 1);
+    assert_eq!(image.get_pixel(1, 0).0, [50, 0, 100, 0]);
+}
+
+#[test]
+fn decoded_rows_match_the_full_render() {
+    let sf = parse_bytes(include_bytes!("../data/raditz.dbl")).unwrap();
+    let full = RgbaImage::try_from(&sf).unwrap();
+
+    let mut rows = Vec::new();
+    sf.decode_rows(|y, row| {
+        assert_eq!(y as usize, rows.len());
+        assert_eq!(row.len(), 128);
+        rows.push(row.to_vec());
+    }).unwrap();
+    assert_eq!(rows.len(), 128);
+
+    let reassembled = RgbaImage::from_fn(128, 128, |x, y| rows[y as usize][x as usize]);
+    assert_eq!(reassembled, full);
+    assert_eq!(full, sf.get_color_table().render(&sf.index_image()).unwrap());
+}
+
+#[test]
+fn out_of_range_indices_fail_to_render() {
+    let lut = SagasColorLUT { colors: vec![SagasColor { r: 1, g: 2, b: 3, a: 255 }; 16] };
+    let indices = IndexImage::from_raw(3, 1, vec![0, 200, 15]).unwrap();
+    assert!(matches!(lut.render(&indices), Err(SagasError::IndexOutOfRange { index: 200, palette_size: 16 })));
+}
+
+#[test]
+fn contact_sheet_layout() {
+    let red = RgbaImage::from_pixel(32, 16, Rgba([255, 0, 0, 255]));
+    let fitted = fit_nearest(&red, 64);
+    assert_eq!(fitted.dimensions(), (64, 32));
+
+    let cells = vec![(red.clone(), "a".to_string()), (red.clone(), "b".to_string()), (red, "c".to_string())];
+    let sheet = contact_sheet(&cells, 2, 64, false);
+    // 4 pixel gaps around and between the cells.
+    assert_eq!(sheet.dimensions(), (4 + 2 * 68, 4 + 2 * 68));
+    // Centered vertically in the first cell.
+    assert_eq!(sheet.get_pixel(4, 4 + 16).0, [255, 0, 0, 255]);
+    assert_ne!(sheet.get_pixel(4, 4).0, [255, 0, 0, 255]);
+
+    let labeled = contact_sheet(&cells, 2, 64, true);
+    assert_eq!(labeled.height(), 4 + 2 * (68 + 10));
+}
+
+#[test]
+fn gamma_keeps_alpha() {
+    let mut image = RgbaImage::from_pixel(1, 1, Rgba([0, 128, 255, 77]));
+    apply_gamma(&mut image, 2.0);
+    assert_eq!(image.get_pixel(0, 0).0, [0, 64, 255, 77]);
+
+    let unchanged = RgbaImage::from_pixel(1, 1, Rgba([3, 128, 250, 1]));
+    let mut copy = unchanged.clone();
+    apply_gamma(&mut copy, 1.0);
+    assert_eq!(copy, unchanged);
+
+    let wide = to_rgba16(&RgbaImage::from_pixel(1, 1, Rgba([0, 128, 255, 128])), 2.2);
+    let [r, g, b, a] = wide.get_pixel(0, 0).0;
+    assert_eq!((r, b, a), (0, 65535, 128 * 257));
+    assert_eq!(g, ((128.0f32 / 255.0).powf(2.2) * 65535.0).round() as u16);
+}
+
+#[test]
+fn png16_round_trips() {
+    let wide = to_rgba16(&RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8 * 100, y as u8 * 200, 7, 255])), 2.2);
+    let mut png = Vec::new();
+    write_png16(&wide, &mut png).unwrap();
+    let decoded = image::load_from_memory(&png).unwrap();
+    assert_eq!(decoded.color(), image::ColorType::Rgba16);
+    assert_eq!(decoded.to_rgba16(), wide);
+}
+
+#[test]
+fn visual_equality_ignores_storage() {
+    const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+    let raditz = parse_bytes(RADITZ).unwrap();
+
+    // Different header padding, same pixels.
+    let mut padded = RADITZ.to_vec();
+    padded[0x70] ^= 0xff;
+    assert!(raditz.visually_eq(&parse_bytes(&padded).unwrap()));
+
+    // Different indices pointing at the same color.
+    let mut fixture = common::Fixture::new(4, 4);
+    fixture.clut = (8, 2);
+    fixture.palette = vec![[10, 20, 30, 0x80]; 16];
+    fixture.image = vec![0; 16];
+    let zeros = parse_bytes(&fixture.build()).unwrap();
+    fixture.image = vec![1; 16];
+    let ones = parse_bytes(&fixture.build()).unwrap();
+    assert!(zeros.visually_eq(&ones));
+
+    // A changed pixel.
+    let mut changed = RADITZ.to_vec();
+    let pixel = 0x4a0 + 5 * 128 + 70;
+    changed[pixel] = if changed[pixel] == 0 { 1 } else { 0 };
+    let changed = parse_bytes(&changed).unwrap();
+    assert!(!raditz.visually_eq(&changed));
+    assert_eq!(raditz.differing_pixels(&changed).unwrap().collect::<Vec<_>>(), [(70, 5)]);
+}
+
+#[cfg(feature = "exr")]
+#[test]
+fn linear_exr_round_trips() {
+    let image = RgbaImage::from_fn(4, 2, |x, y| Rgba([x as u8 * 60, y as u8 * 255, 128, 200]));
+    let mut exr = Vec::new();
+    dbz_sagas_extractor::write_linear_exr(&image, std::io::Cursor::new(&mut exr)).unwrap();
+
+    let decoded = image::load_from_memory(&exr).unwrap().to_rgba32f();
+    assert_eq!(decoded.dimensions(), (4, 2));
+    for (x, y, pixel) in decoded.enumerate_pixels() {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let expected = SagasColor { r, g, b, a }.to_linear_rgba();
+        for (got, want) in pixel.0.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn decode_to_rgba_returns_packed_pixels() {
+    let data = common::Fixture::new(3, 2).build();
+    let (width, height, pixels) = decode_to_rgba(&data).unwrap();
+    assert_eq!((width, height), (3, 2));
+    assert_eq!(pixels.len(), 3 * 2 * 4);
+    assert_eq!(pixels, RgbaImage::try_from(&parse_bytes(&data).unwrap()).unwrap().into_raw());
+
+    assert!(decode_to_rgba(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn planar_output_splits_the_channels() {
+    let mut image = RgbaImage::new(2, 1);
+    image.put_pixel(0, 0, Rgba([1, 2, 3, 4]));
+    image.put_pixel(1, 0, Rgba([5, 6, 7, 8]));
+    assert_eq!(to_planar(&image, true), [1, 5, 2, 6, 3, 7, 4, 8]);
+    assert_eq!(to_planar(&image, false), [1, 5, 2, 6, 3, 7]);
+}
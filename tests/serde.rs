@@ -0,0 +1,22 @@
+#![cfg(feature = "serde")]
+
+use std::io::Cursor;
+
+use dbz_sagas_extractor::{FromReader, SagasColorLUT, SagasFile, SagasHeader};
+
+const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+
+#[test]
+fn header_and_palette_round_trip_through_json() {
+    let sf = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+
+    let json = serde_json::to_value(sf.get_header()).unwrap();
+    assert_eq!(json["string0"], "c:\\dev\\dbz\\data_ps2\\sprites\\fac");
+    assert_eq!(json["width"], 128);
+    let header: SagasHeader = serde_json::from_value(json).unwrap();
+    assert_eq!(header.string1, sf.get_header().string1);
+
+    let json = serde_json::to_string(sf.get_color_table()).unwrap();
+    let lut: SagasColorLUT = serde_json::from_str(&json).unwrap();
+    assert_eq!(lut.colors.len(), 256);
+}
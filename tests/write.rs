@@ -0,0 +1,171 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::Fixture;
+use dbz_sagas_extractor::{encode_rgba, pack_4bpp, FromReader, SagasColorLUT, SagasError, SagasFile, ToWriter};
+
+const RADITZ: &[u8] = include_bytes!("../data/raditz.dbl");
+
+fn round_trip(data: &[u8]) -> Vec<u8> {
+    let sf = SagasFile::from_reader(&mut Cursor::new(data)).unwrap();
+    let mut out = Cursor::new(Vec::new());
+    sf.to_writer(&mut out).unwrap();
+    out.into_inner()
+}
+
+#[test]
+fn raditz_round_trips_byte_for_byte() {
+    assert!(round_trip(RADITZ) == RADITZ);
+}
+
+#[test]
+fn fixtures_round_trip_byte_for_byte() {
+    let data = Fixture::new(32, 16).build();
+    assert_eq!(round_trip(&data), data);
+
+    let mut fixture = Fixture::new(8, 8);
+    fixture.clut = (8, 2);
+    fixture.palette.truncate(16);
+    fixture.image = pack_4bpp(&(0..64).map(|i| (i % 16) as u8).collect::<Vec<_>>());
+    fixture.texture = "Bit04_000.tga";
+    let data = fixture.build();
+    assert_eq!(round_trip(&data), data);
+}
+
+fn render(sf: &SagasFile) -> image::RgbaImage {
+    let colors = &sf.get_color_table().colors;
+    let header = sf.get_header();
+    image::RgbaImage::from_fn(header.width as u32, header.height as u32, |x, y| {
+        let c = colors[sf.get_image()[(x + y * header.width as u32) as usize] as usize];
+        image::Rgba([c.r, c.g, c.b, c.a])
+    })
+}
+
+#[test]
+fn encoded_image_decodes_to_the_same_pixels() {
+    let original = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let rendered = render(&original);
+
+    for template in [None, Some(&original)] {
+        let encoded = encode_rgba(&rendered, template).unwrap();
+        let mut data = Cursor::new(Vec::new());
+        encoded.to_writer(&mut data).unwrap();
+
+        let decoded = SagasFile::from_reader(&mut Cursor::new(data.into_inner())).unwrap();
+        assert_eq!(render(&decoded), rendered);
+    }
+}
+
+#[test]
+fn encoding_quantizes_large_palettes() {
+    let image = image::RgbaImage::from_fn(32, 32, |x, y| image::Rgba([(x * 8) as u8, (y * 8) as u8, 0, 255]));
+    let encoded = encode_rgba(&image, None).unwrap();
+    assert_eq!(encoded.get_color_table().colors.len(), 256);
+    assert_eq!(encoded.get_image().len(), 32 * 32);
+}
+
+#[test]
+fn quantizing_keeps_transparent_pixels_transparent() {
+    // A cutout: colorful but invisible background on the left, too many
+    // opaque colors for an exact palette on the right.
+    let image = image::RgbaImage::from_fn(48, 32, |x, y| {
+        image::Rgba([(x * 5) as u8, (y * 8) as u8, 200, if x < 16 { 0 } else { 255 }])
+    });
+    let encoded = encode_rgba(&image, None).unwrap();
+    let colors = &encoded.get_color_table().colors;
+    for (i, &index) in encoded.get_image().iter().enumerate() {
+        let expected = if i % 48 < 16 { 0 } else { 255 };
+        assert_eq!(colors[index as usize].a, expected, "pixel {}", i);
+    }
+}
+
+#[test]
+fn from_parts_builds_a_writable_file() {
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = || SagasColorLUT { colors: raditz.get_color_table().colors.clone() };
+    let sf = SagasFile::from_parts(raditz.get_header().clone(), lut(), raditz.get_image().to_vec()).unwrap();
+
+    let mut out = Cursor::new(Vec::new());
+    sf.to_writer(&mut out).unwrap();
+    let reread = SagasFile::from_reader(&mut Cursor::new(out.into_inner())).unwrap();
+    assert_eq!(reread.get_image(), raditz.get_image());
+    assert_eq!(reread.content_hash(), raditz.content_hash());
+}
+
+#[test]
+fn from_parts_checks_the_image() {
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = || SagasColorLUT { colors: raditz.get_color_table().colors.clone() };
+    let header = raditz.get_header();
+
+    let short = raditz.get_image()[1..].to_vec();
+    assert!(matches!(SagasFile::from_parts(header.clone(), lut(), short), Err(SagasError::BadDimensions)));
+
+    let small_palette = SagasColorLUT { colors: lut().colors[..4].to_vec() };
+    assert!(matches!(SagasFile::from_parts(header.clone(), small_palette, raditz.get_image().to_vec()),
+                     Err(SagasError::IndexOutOfRange { palette_size: 4, .. })));
+}
+
+#[test]
+fn from_parts_rejects_empty_images() {
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = || SagasColorLUT { colors: raditz.get_color_table().colors.clone() };
+    for (width, height) in [(0, 0), (0, 128), (128, 0)] {
+        let mut header = raditz.get_header().clone();
+        (header.width, header.height) = (width, height);
+        assert!(matches!(SagasFile::from_parts(header, lut(), Vec::new()), Err(SagasError::BadDimensions)),
+                "{}x{}", width, height);
+    }
+}
+
+#[test]
+fn from_parts_checks_the_palette_size() {
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let mut colors = raditz.get_color_table().colors.clone();
+    colors.push(colors[0]);
+    let image = raditz.get_image().to_vec();
+    assert!(matches!(SagasFile::from_parts(raditz.get_header().clone(), SagasColorLUT { colors }, image),
+                     Err(SagasError::PaletteSizeMismatch { expected: 256, found: 257 })));
+}
+
+#[test]
+fn from_parts_checks_indices_fit_the_depth() {
+    // A 4-bit image that still has a 256 color palette, so only the depth
+    // rules out index 16.
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let lut = || SagasColorLUT { colors: raditz.get_color_table().colors.clone() };
+    let mut header = raditz.get_header().clone();
+    header.string1 = std::ffi::CString::new("Bit04_000.tga").unwrap();
+    let mut image = vec![15; 128 * 128];
+    assert!(SagasFile::from_parts(header.clone(), lut(), image.clone()).is_ok());
+
+    image[5] = 16;
+    assert!(matches!(SagasFile::from_parts(header, lut(), image),
+                     Err(SagasError::IndexOutOfRange { index: 16, palette_size: 16 })));
+}
+
+#[test]
+fn encoding_lays_out_a_template_with_the_image_first() {
+    // raditz with the image moved in front of the palette.
+    let raditz = SagasFile::from_reader(&mut Cursor::new(RADITZ)).unwrap();
+    let mut header = raditz.get_header().clone();
+    header.image_offset = 0xa0;
+    header.color_table_offset = 0xa0 + 128 * 128;
+    let lut = SagasColorLUT { colors: raditz.get_color_table().colors.clone() };
+    let mut data = Cursor::new(Vec::new());
+    SagasFile::from_parts(header, lut, raditz.get_image().to_vec()).unwrap().to_writer(&mut data).unwrap();
+    let template = SagasFile::from_reader(&mut Cursor::new(data.into_inner())).unwrap();
+
+    // Four times the template's image, which would run over its palette.
+    let image = image::RgbaImage::from_fn(256, 256, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+    let encoded = encode_rgba(&image, Some(&template)).unwrap();
+    let header = encoded.get_header();
+    assert_eq!(header.color_table_offset, 0xa0);
+    assert_eq!(header.image_offset, 0xa0 + 1024);
+
+    let mut data = Cursor::new(Vec::new());
+    encoded.to_writer(&mut data).unwrap();
+    let decoded = SagasFile::from_reader(&mut Cursor::new(data.into_inner())).unwrap();
+    assert_eq!(render(&decoded), render(&encoded));
+}